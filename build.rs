@@ -0,0 +1,31 @@
+//! Records the kafka crate version and the git commit for `kafku --version`.
+use std::fs;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut lines = lock.lines();
+    let kafka = lines
+        .find(|line| *line == "name = \"kafka\"")
+        .and_then(|_| lines.next())
+        .and_then(|line| line.strip_prefix("version = \""))
+        .and_then(|version| version.strip_suffix('"'));
+    if let Some(version) = kafka {
+        println!("cargo:rustc-env=KAFKU_KAFKA_VERSION={}", version);
+    }
+
+    // Source tarballs have no git history to ask.
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=KAFKU_GIT_HASH={}", hash.trim());
+    }
+}
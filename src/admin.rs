@@ -0,0 +1,612 @@
+//! A minimal client for the admin requests the kafka crate doesn't implement.
+//! Requests are encoded by hand against the Kafka wire protocol and sent over
+//! a short-lived connection.
+use log::{debug, warn};
+use openssl::ssl::{SslConnector, SslStream};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use thiserror::Error;
+
+const API_METADATA: i16 = 3;
+const API_OFFSET_FETCH: i16 = 9;
+const API_FIND_COORDINATOR: i16 = 10;
+const API_LIST_GROUPS: i16 = 16;
+const API_CREATE_TOPICS: i16 = 19;
+const API_DELETE_TOPICS: i16 = 20;
+const API_DESCRIBE_CONFIGS: i16 = 32;
+const API_DESCRIBE_LOG_DIRS: i16 = 35;
+
+const RESOURCE_TOPIC: i8 = 2;
+
+const CLIENT_ID: &str = "kafku";
+const REQUEST_TIMEOUT_MS: i32 = 10_000;
+/// How long a single socket operation may take before the broker counts as
+/// unreachable. Generous enough for the broker-side request timeout above.
+const SOCKET_TIMEOUT: Duration = Duration::from_millis(REQUEST_TIMEOUT_MS as u64 + 5_000);
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("TLS handshake with {0} failed: {1}")]
+    Tls(String, String),
+    #[error("malformed response from broker")]
+    Malformed,
+    #[error("broker rejected the request: {0}")]
+    Broker(&'static str),
+    #[error("no broker could be reached")]
+    NoBrokerReachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Broker {
+    pub id: i32,
+    pub host: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterMetadata {
+    pub brokers: Vec<Broker>,
+    pub controller_id: i32,
+}
+
+impl ClusterMetadata {
+    fn controller(&self) -> Option<&Broker> {
+        self.brokers.iter().find(|b| b.id == self.controller_id)
+    }
+}
+
+/// An offset a consumer group has committed for one partition.
+#[derive(Debug, Clone)]
+pub struct GroupOffset {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// The replica set of one partition.
+#[derive(Debug, Clone)]
+pub struct PartitionReplicas {
+    pub topic: String,
+    pub partition: i32,
+    /// Ids of the brokers assigned a copy of the partition.
+    pub replicas: Vec<i32>,
+    /// Ids of the replicas caught up with the leader.
+    pub isr: Vec<i32>,
+}
+
+/// The bytes one broker holds on disk for a partition.
+#[derive(Debug, Clone)]
+pub struct ReplicaSize {
+    pub topic: String,
+    pub partition: i32,
+    pub size: i64,
+}
+
+/// One configuration entry of a topic.
+#[derive(Debug, Clone)]
+pub struct TopicConfig {
+    pub name: String,
+    /// None for sensitive entries, whose values brokers don't reveal.
+    pub value: Option<String>,
+    /// Whether the value is the broker's default rather than set on the topic.
+    pub is_default: bool,
+}
+
+#[derive(Clone)]
+pub struct AdminClient {
+    hosts: Vec<String>,
+    tls: Option<SslConnector>,
+    connect_timeout: Duration,
+}
+
+impl AdminClient {
+    pub fn new(
+        hosts: Vec<String>,
+        tls: Option<SslConnector>,
+        connect_timeout: Duration,
+    ) -> AdminClient {
+        AdminClient {
+            hosts,
+            tls,
+            connect_timeout,
+        }
+    }
+
+    /// Loads the broker list and controller id from the first reachable
+    /// bootstrap host.
+    pub fn metadata(&self) -> Result<ClusterMetadata, AdminError> {
+        let mut body = Encoder::default();
+        // An empty topic array asks for no topics, keeping the response small.
+        body.i32(0);
+        let response = self.request_any(API_METADATA, 1, body)?;
+        let mut d = Decoder::new(&response);
+        let brokers = d.array(|d| {
+            let id = d.i32()?;
+            let host = d.string()?;
+            let port = d.i32()?;
+            d.nullable_string()?;
+            Ok(Broker {
+                id,
+                host: format!("{}:{}", host, port),
+            })
+        })?;
+        let controller_id = d.i32()?;
+        Ok(ClusterMetadata {
+            brokers,
+            controller_id,
+        })
+    }
+
+    /// Loads the replicas and in-sync replicas of every partition, which the
+    /// kafka crate's metadata leaves out.
+    pub fn partition_replicas(&self) -> Result<Vec<PartitionReplicas>, AdminError> {
+        let mut body = Encoder::default();
+        // A null topic array asks for every topic.
+        body.i32(-1);
+        let response = self.request_any(API_METADATA, 1, body)?;
+        let mut d = Decoder::new(&response);
+        d.array(|d| {
+            d.i32()?; // node id
+            d.string()?; // host
+            d.i32()?; // port
+            d.nullable_string()?; // rack
+            Ok(())
+        })?;
+        d.i32()?; // controller id
+        let topics = d.array(|d| {
+            d.i16()?; // error code
+            let topic = d.string()?;
+            d.bool()?; // internal
+            d.array(|d| {
+                d.i16()?; // error code
+                let partition = d.i32()?;
+                d.i32()?; // leader
+                Ok(PartitionReplicas {
+                    topic: topic.clone(),
+                    partition,
+                    replicas: d.array(|d| d.i32())?,
+                    isr: d.array(|d| d.i32())?,
+                })
+            })
+        })?;
+        Ok(topics.into_iter().flatten().collect())
+    }
+
+    pub fn create_topic(
+        &self,
+        name: &str,
+        partitions: i32,
+        replication_factor: i16,
+    ) -> Result<(), AdminError> {
+        let mut body = Encoder::default();
+        body.i32(1);
+        body.string(name);
+        body.i32(partitions);
+        body.i16(replication_factor);
+        body.i32(0); // replica assignments
+        body.i32(0); // configs
+        body.i32(REQUEST_TIMEOUT_MS);
+        let response = self.request_controller(API_CREATE_TOPICS, 0, body)?;
+        topic_errors(&response)
+    }
+
+    pub fn delete_topic(&self, name: &str) -> Result<(), AdminError> {
+        let mut body = Encoder::default();
+        body.i32(1);
+        body.string(name);
+        body.i32(REQUEST_TIMEOUT_MS);
+        let response = self.request_controller(API_DELETE_TOPICS, 0, body)?;
+        topic_errors(&response)
+    }
+
+    /// Loads every configuration entry of topic `name`. Brokers older than
+    /// Kafka 0.11 don't know DescribeConfigs and drop the connection instead.
+    pub fn describe_topic_configs(&self, name: &str) -> Result<Vec<TopicConfig>, AdminError> {
+        let mut body = Encoder::default();
+        body.i32(1);
+        body.i8(RESOURCE_TOPIC);
+        body.string(name);
+        // A null name array asks for all entries.
+        body.i32(-1);
+        let response = self.request_any(API_DESCRIBE_CONFIGS, 0, body)?;
+        let mut d = Decoder::new(&response);
+        d.i32()?; // throttle time
+        let resources = d.array(|d| {
+            check(d.i16()?)?;
+            d.nullable_string()?; // error message
+            d.i8()?; // resource type
+            d.string()?; // resource name
+            d.array(|d| {
+                let name = d.string()?;
+                let value = d.nullable_string()?;
+                d.bool()?; // read only
+                let is_default = d.bool()?;
+                d.bool()?; // sensitive
+                Ok(TopicConfig {
+                    name,
+                    value,
+                    is_default,
+                })
+            })
+        })?;
+        let mut configs: Vec<TopicConfig> = resources.into_iter().flatten().collect();
+        configs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(configs)
+    }
+
+    /// Loads the size on disk of every partition replica from each broker's
+    /// log directories. Brokers that can't say are logged and left out, such
+    /// as those older than Kafka 1.0, which don't know DescribeLogDirs and
+    /// drop the connection instead.
+    pub fn log_dir_sizes(&self) -> Result<Vec<ReplicaSize>, AdminError> {
+        let mut sizes = vec![];
+        for broker in self.metadata()?.brokers {
+            // One broker down shouldn't hide what the others report.
+            match self.broker_log_dir_sizes(&broker.host) {
+                Ok(broker_sizes) => sizes.extend(broker_sizes),
+                Err(e) => warn!("could not load log dir sizes from {}: {}", broker.host, e),
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// The sizes of the replicas in `host`'s log directories.
+    fn broker_log_dir_sizes(&self, host: &str) -> Result<Vec<ReplicaSize>, AdminError> {
+        let mut body = Encoder::default();
+        // A null topic array asks for every topic.
+        body.i32(-1);
+        let response = self.request(host, API_DESCRIBE_LOG_DIRS, 0, &body.0)?;
+        let mut d = Decoder::new(&response);
+        d.i32()?; // throttle time
+        let dirs = d.array(|d| {
+            // An offline directory reports an error and no topics.
+            let error = d.i16()?;
+            d.string()?; // path
+            let topics = d.array(|d| {
+                let topic = d.string()?;
+                d.array(|d| {
+                    let partition = d.i32()?;
+                    let size = d.i64()?;
+                    d.i64()?; // offset lag
+
+                    // A future replica is a copy still being moved
+                    // between directories.
+                    let is_future = d.bool()?;
+                    Ok((!is_future).then(|| ReplicaSize {
+                        topic: topic.clone(),
+                        partition,
+                        size,
+                    }))
+                })
+            })?;
+            Ok(if error == 0 { topics } else { vec![] })
+        })?;
+        Ok(dirs.into_iter().flatten().flatten().flatten().collect())
+    }
+
+    /// Checks that `host` answers a metadata request within a couple of
+    /// seconds.
+    pub fn ping(&self, host: &str) -> Result<(), AdminError> {
+        let mut body = Encoder::default();
+        body.i32(0);
+        self.exchange(host, API_METADATA, 1, &body.0, PING_TIMEOUT)
+            .map(|_| ())
+    }
+
+    /// Lists the groups of every broker, since each one only knows about the
+    /// groups it coordinates.
+    pub fn list_groups(&self) -> Result<Vec<String>, AdminError> {
+        let mut groups = vec![];
+        for broker in self.metadata()?.brokers {
+            let response = self.request(&broker.host, API_LIST_GROUPS, 0, &[])?;
+            let mut d = Decoder::new(&response);
+            check(d.i16()?)?;
+            groups.extend(d.array(|d| {
+                let group = d.string()?;
+                d.string()?; // protocol type
+                Ok(group)
+            })?);
+        }
+        groups.sort();
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Loads every offset `group` has committed from its coordinator.
+    pub fn group_offsets(&self, group: &str) -> Result<Vec<GroupOffset>, AdminError> {
+        let coordinator = self.find_coordinator(group)?;
+        let mut body = Encoder::default();
+        body.string(group);
+        // A null topic array asks for all topics, which needs version 2.
+        body.i32(-1);
+        let response = self.request(&coordinator, API_OFFSET_FETCH, 2, &body.0)?;
+        let mut d = Decoder::new(&response);
+        let topics = d.array(|d| {
+            let topic = d.string()?;
+            let partitions = d.array(|d| {
+                let partition = d.i32()?;
+                let offset = d.i64()?;
+                d.nullable_string()?; // metadata
+                let error = d.i16()?;
+                // -1 marks partitions without a committed offset.
+                Ok((error == 0 && offset >= 0).then(|| GroupOffset {
+                    topic: topic.clone(),
+                    partition,
+                    offset,
+                }))
+            })?;
+            Ok(partitions)
+        })?;
+        check(d.i16()?)?;
+        let mut offsets: Vec<GroupOffset> = topics.into_iter().flatten().flatten().collect();
+        offsets.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        Ok(offsets)
+    }
+
+    fn find_coordinator(&self, group: &str) -> Result<String, AdminError> {
+        let mut body = Encoder::default();
+        body.string(group);
+        let response = self.request_any(API_FIND_COORDINATOR, 0, body)?;
+        let mut d = Decoder::new(&response);
+        check(d.i16()?)?;
+        d.i32()?; // node id
+        let host = d.string()?;
+        let port = d.i32()?;
+        Ok(format!("{}:{}", host, port))
+    }
+
+    /// Sends a request to the cluster controller, which topic-level admin
+    /// requests must go to.
+    fn request_controller(
+        &self,
+        api_key: i16,
+        version: i16,
+        body: Encoder,
+    ) -> Result<Vec<u8>, AdminError> {
+        let metadata = self.metadata()?;
+        let controller = metadata.controller().ok_or(AdminError::NoBrokerReachable)?;
+        self.request(&controller.host, api_key, version, &body.0)
+    }
+
+    fn request_any(
+        &self,
+        api_key: i16,
+        version: i16,
+        body: Encoder,
+    ) -> Result<Vec<u8>, AdminError> {
+        let mut last_error = AdminError::NoBrokerReachable;
+        for host in &self.hosts {
+            match self.request(host, api_key, version, &body.0) {
+                Ok(response) => return Ok(response),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn request(
+        &self,
+        host: &str,
+        api_key: i16,
+        version: i16,
+        body: &[u8],
+    ) -> Result<Vec<u8>, AdminError> {
+        self.exchange(host, api_key, version, body, SOCKET_TIMEOUT)
+    }
+
+    fn exchange(
+        &self,
+        host: &str,
+        api_key: i16,
+        version: i16,
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, AdminError> {
+        let correlation_id = 1;
+        let mut header = Encoder::default();
+        header.i16(api_key);
+        header.i16(version);
+        header.i32(correlation_id);
+        header.string(CLIENT_ID);
+
+        debug!("sending request {} v{} to {}", api_key, version, host);
+        let mut stream = self.connect(host, timeout)?;
+        let size = (header.0.len() + body.len()) as i32;
+        stream.write_all(&size.to_be_bytes())?;
+        stream.write_all(&header.0)?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut size = [0; 4];
+        stream.read_exact(&mut size)?;
+        let mut response = vec![0; i32::from_be_bytes(size).max(0) as usize];
+        stream.read_exact(&mut response)?;
+        let mut d = Decoder::new(&response);
+        if d.i32()? != correlation_id {
+            return Err(AdminError::Malformed);
+        }
+        Ok(response[4..].to_vec())
+    }
+
+    fn connect(&self, host: &str, timeout: Duration) -> Result<Stream, AdminError> {
+        let addr = host
+            .to_socket_addrs()?
+            .next()
+            .ok_or(AdminError::NoBrokerReachable)?;
+        let tcp = TcpStream::connect_timeout(&addr, timeout.min(self.connect_timeout))?;
+        tcp.set_read_timeout(Some(timeout))?;
+        tcp.set_write_timeout(Some(timeout))?;
+        match &self.tls {
+            Some(connector) => {
+                let domain = host.rsplit_once(':').map(|(h, _)| h).unwrap_or(host);
+                let domain = domain.trim_start_matches('[').trim_end_matches(']');
+                connector
+                    .connect(domain, tcp)
+                    .map(Stream::Tls)
+                    .map_err(|e| AdminError::Tls(host.to_string(), e.to_string()))
+            }
+            None => Ok(Stream::Plain(tcp)),
+        }
+    }
+}
+
+/// Checks a response made of an array of `(topic, error_code)` pairs, as
+/// returned by CreateTopics and DeleteTopics.
+fn topic_errors(response: &[u8]) -> Result<(), AdminError> {
+    let mut d = Decoder::new(response);
+    let errors = d.array(|d| {
+        d.string()?;
+        d.i16()
+    })?;
+    errors.into_iter().try_for_each(check)
+}
+
+fn check(code: i16) -> Result<(), AdminError> {
+    match code {
+        0 => Ok(()),
+        code => Err(AdminError::Broker(error_name(code))),
+    }
+}
+
+fn error_name(code: i16) -> &'static str {
+    match code {
+        3 => "unknown topic or partition",
+        7 => "request timed out",
+        14 => "coordinator load in progress",
+        15 => "coordinator not available",
+        16 => "not coordinator",
+        17 => "invalid topic name",
+        29 => "topic authorization failed",
+        30 => "group authorization failed",
+        31 => "cluster authorization failed",
+        35 => "unsupported version",
+        36 => "topic already exists",
+        37 => "invalid number of partitions",
+        38 => "invalid replication factor",
+        39 => "invalid replica assignment",
+        40 => "invalid config",
+        41 => "not the controller",
+        42 => "invalid request",
+        _ => "unexpected error code",
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(SslStream<TcpStream>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn i8(&mut self, value: i8) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.i16(value.len() as i16);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Decoder<'a> {
+        Decoder { buf }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], AdminError> {
+        if self.buf.len() < n {
+            return Err(AdminError::Malformed);
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn i8(&mut self) -> Result<i8, AdminError> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    fn bool(&mut self) -> Result<bool, AdminError> {
+        Ok(self.i8()? != 0)
+    }
+
+    fn i16(&mut self) -> Result<i16, AdminError> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, AdminError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    fn i64(&mut self) -> Result<i64, AdminError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<String, AdminError> {
+        self.nullable_string().map(Option::unwrap_or_default)
+    }
+
+    fn nullable_string(&mut self) -> Result<Option<String>, AdminError> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        let bytes = self.take(len as usize)?;
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn array<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Decoder<'a>) -> Result<T, AdminError>,
+    ) -> Result<Vec<T>, AdminError> {
+        let len = self.i32()?;
+        (0..len.max(0)).map(|_| item(self)).collect()
+    }
+}
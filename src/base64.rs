@@ -0,0 +1,79 @@
+//! Standard, padded base64, for carrying raw bytes through JSON.
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The bytes `text` encodes, or None when it isn't padded base64.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (number, chunk) in text.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        // Padding only ends the text, and never fills more than two places.
+        if padding > 2 || (padding > 0 && number + 1 < text.len() / 4) {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let digit = ALPHABET.iter().position(|&a| a == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            decoded.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_with_padding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(&[0xff, 0x00, 0xfe]), "/wD+");
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                decode(&encode(&bytes[..len])).as_deref(),
+                Some(&bytes[..len])
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        assert_eq!(decode("Zg="), None);
+        assert_eq!(decode("Zg*="), None);
+        assert_eq!(decode("Z==="), None);
+        assert_eq!(decode("Zg==Zg=="), None);
+    }
+}
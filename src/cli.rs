@@ -0,0 +1,483 @@
+use crate::config::TICK_RATE_MS;
+use kafka::consumer::FetchOffset;
+use std::path::PathBuf;
+use std::slice::Iter;
+use thiserror::Error;
+
+pub const USAGE: &str = "Usage:
+    kafku [profile]                  open the TUI for a profile from ~/.kafka/.config or
+                                     ~/.kafka/config.d/*.json, defaulting to the one with
+                                     \"default\": true, or else to the one used last
+        --read-only                  disable producing, creating and deleting topics and
+                                     resetting offsets
+        --tick-rate <ms>             redraw every ms milliseconds, 50 to 1000 (default 200)
+        --no-commit                  pull without committing offsets, toggled with C
+    kafku init                       write an example config file
+    kafku --version                  print the version of kafku and of its kafka client
+    kafku topics <profile>           print the topic names, one per line
+        --json                       print topics with their partitions as JSON instead
+    kafku lag <profile> <group>      print the group's lag per partition: topic, partition,
+                                     committed offset, latest offset and lag, tab separated
+        --json                       print the lag as JSON instead
+        --threshold <n>              exit with status 1 if the total lag exceeds n
+    kafku consume <profile> <topic>  print messages from a topic, one per line
+        --max <n>                    stop after n messages
+        --from <earliest|latest>     where to start without a committed offset (default earliest)
+        --key-filter <prefix>        only print messages whose key starts with prefix, or
+                                     matches the regex after a leading /
+        --no-commit                  start from the group's offsets but don't move them
+    kafku produce <profile> <topic>  send each line of stdin as a message
+        --key-separator <sep>        split lines into key and value at sep (\\t for a tab)
+    kafku replay <profile> <topic> <file>
+                                     send the key and value of each JSON line in file, as
+                                     written by Export
+        --rate <n>                   send at most n messages per second
+
+Options:
+    --config <path>                  read profiles from path alone, instead of ~/.kafka/.config
+                                     and config.d; also where kafku init writes its example
+
+Environment:
+    KAFKU_CONFIG                     config file to read, like --config
+    KAFKU_BROKER                     comma separated brokers, overriding the profile's
+    KAFKU_LOG                        log at this level (e.g. debug) to ~/.kafka/kafku.log";
+
+#[derive(Debug)]
+pub enum Command {
+    Init,
+    Version,
+    Tui {
+        /// None reopens the profile used last.
+        profile: Option<String>,
+        read_only: bool,
+        /// Overrides the profile's tick rate.
+        tick_rate_ms: Option<u64>,
+        no_commit: bool,
+    },
+    Topics {
+        profile: String,
+        json: bool,
+    },
+    Lag {
+        profile: String,
+        group: String,
+        json: bool,
+        threshold: Option<i64>,
+    },
+    Consume {
+        profile: String,
+        topic: String,
+        max: Option<usize>,
+        from: FetchOffset,
+        key_filter: Option<String>,
+        no_commit: bool,
+    },
+    Produce {
+        profile: String,
+        topic: String,
+        key_separator: Option<String>,
+    },
+    Replay {
+        profile: String,
+        topic: String,
+        file: PathBuf,
+        /// At most this many messages per second.
+        rate: Option<u32>,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum UsageError {
+    #[error("missing profile name")]
+    MissingProfile,
+    #[error("missing topic name")]
+    MissingTopic,
+    #[error("missing group name")]
+    MissingGroup,
+    #[error("missing file name")]
+    MissingFile,
+    #[error("missing value for {0}")]
+    MissingValue(&'static str),
+    #[error("invalid value for {0}: {1}")]
+    InvalidValue(&'static str, String),
+    #[error("unexpected argument: {0}")]
+    Unexpected(String),
+}
+
+/// Removes `--config <path>` from `args`, wherever it appears, returning
+/// the path. Every command accepts it.
+pub fn take_config(args: &mut Vec<String>) -> Result<Option<PathBuf>, UsageError> {
+    let at = match args.iter().position(|arg| arg == "--config") {
+        Some(at) => at,
+        None => return Ok(None),
+    };
+    if at + 1 == args.len() {
+        return Err(UsageError::MissingValue("--config"));
+    }
+    let path = args.remove(at + 1);
+    args.remove(at);
+    Ok(Some(PathBuf::from(path)))
+}
+
+/// Parses the arguments following the binary name.
+pub fn parse(args: &[String]) -> Result<Command, UsageError> {
+    let mut rest = args.iter();
+    match rest.next().map(String::as_str) {
+        Some("init") => no_arguments(rest, Command::Init),
+        Some("--version" | "-V") => no_arguments(rest, Command::Version),
+        Some("topics") => parse_topics(rest),
+        Some("lag") => parse_lag(rest),
+        Some("consume") => parse_consume(rest),
+        Some("produce") => parse_produce(rest),
+        Some("replay") => parse_replay(rest),
+        _ => parse_tui(args.iter()),
+    }
+}
+
+/// `command`, unless arguments it doesn't take follow it.
+fn no_arguments(mut args: Iter<String>, command: Command) -> Result<Command, UsageError> {
+    match args.next() {
+        Some(extra) => Err(UsageError::Unexpected(extra.clone())),
+        None => Ok(command),
+    }
+}
+
+fn parse_tui(mut args: Iter<String>) -> Result<Command, UsageError> {
+    let mut profile = None;
+    let mut read_only = false;
+    let mut tick_rate_ms = None;
+    let mut no_commit = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--read-only" => read_only = true,
+            "--no-commit" => no_commit = true,
+            "--tick-rate" => {
+                let value = flag_value(&mut args, "--tick-rate")?;
+                tick_rate_ms = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|ms| TICK_RATE_MS.contains(ms))
+                        .ok_or_else(|| UsageError::InvalidValue("--tick-rate", value.clone()))?,
+                );
+            }
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ if profile.is_none() => profile = Some(arg.clone()),
+            _ => return Err(UsageError::Unexpected(arg.clone())),
+        }
+    }
+    Ok(Command::Tui {
+        profile,
+        read_only,
+        tick_rate_ms,
+        no_commit,
+    })
+}
+
+fn parse_topics(args: Iter<String>) -> Result<Command, UsageError> {
+    let mut profile = None;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ if profile.is_none() => profile = Some(arg.clone()),
+            _ => return Err(UsageError::Unexpected(arg.clone())),
+        }
+    }
+    Ok(Command::Topics {
+        profile: profile.ok_or(UsageError::MissingProfile)?,
+        json,
+    })
+}
+
+fn parse_lag(mut args: Iter<String>) -> Result<Command, UsageError> {
+    let mut positional = vec![];
+    let mut json = false;
+    let mut threshold = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--threshold" => {
+                let value = flag_value(&mut args, "--threshold")?;
+                threshold = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|threshold| *threshold >= 0)
+                        .ok_or_else(|| UsageError::InvalidValue("--threshold", value.clone()))?,
+                );
+            }
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let profile = positional.next().ok_or(UsageError::MissingProfile)?;
+    let group = positional.next().ok_or(UsageError::MissingGroup)?;
+    if let Some(extra) = positional.next() {
+        return Err(UsageError::Unexpected(extra));
+    }
+    Ok(Command::Lag {
+        profile,
+        group,
+        json,
+        threshold,
+    })
+}
+
+fn parse_consume(mut args: Iter<String>) -> Result<Command, UsageError> {
+    let mut positional = vec![];
+    let mut max = None;
+    let mut from = FetchOffset::Earliest;
+    let mut key_filter = None;
+    let mut no_commit = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-commit" => no_commit = true,
+            "--max" => {
+                let value = flag_value(&mut args, "--max")?;
+                max = Some(
+                    value
+                        .parse()
+                        .map_err(|_| UsageError::InvalidValue("--max", value.clone()))?,
+                );
+            }
+            "--from" => {
+                from = match flag_value(&mut args, "--from")?.as_str() {
+                    "earliest" => FetchOffset::Earliest,
+                    "latest" => FetchOffset::Latest,
+                    other => return Err(UsageError::InvalidValue("--from", other.to_string())),
+                }
+            }
+            "--key-filter" => key_filter = Some(flag_value(&mut args, "--key-filter")?.clone()),
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let (profile, topic) = profile_and_topic(positional)?;
+    Ok(Command::Consume {
+        profile,
+        topic,
+        max,
+        from,
+        key_filter,
+        no_commit,
+    })
+}
+
+fn parse_produce(mut args: Iter<String>) -> Result<Command, UsageError> {
+    let mut positional = vec![];
+    let mut key_separator = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key-separator" => {
+                let value = flag_value(&mut args, "--key-separator")?;
+                if value.is_empty() {
+                    return Err(UsageError::InvalidValue("--key-separator", value.clone()));
+                }
+                // Lets a tab be passed without the shell's $'\t' quoting.
+                key_separator = Some(value.replace("\\t", "\t"));
+            }
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let (profile, topic) = profile_and_topic(positional)?;
+    Ok(Command::Produce {
+        profile,
+        topic,
+        key_separator,
+    })
+}
+
+fn parse_replay(mut args: Iter<String>) -> Result<Command, UsageError> {
+    let mut positional = vec![];
+    let mut rate = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rate" => {
+                let value = flag_value(&mut args, "--rate")?;
+                rate = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|rate| *rate > 0)
+                        .ok_or_else(|| UsageError::InvalidValue("--rate", value.clone()))?,
+                );
+            }
+            flag if flag.starts_with('-') => return Err(UsageError::Unexpected(flag.to_string())),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let file = if positional.len() > 2 {
+        Some(PathBuf::from(positional.remove(2)))
+    } else {
+        None
+    };
+    let (profile, topic) = profile_and_topic(positional)?;
+    Ok(Command::Replay {
+        profile,
+        topic,
+        file: file.ok_or(UsageError::MissingFile)?,
+        rate,
+    })
+}
+
+/// Splits the positional arguments of `consume`, `produce` and `replay`.
+fn profile_and_topic(positional: Vec<String>) -> Result<(String, String), UsageError> {
+    let mut positional = positional.into_iter();
+    let profile = positional.next().ok_or(UsageError::MissingProfile)?;
+    let topic = positional.next().ok_or(UsageError::MissingTopic)?;
+    match positional.next() {
+        Some(extra) => Err(UsageError::Unexpected(extra)),
+        None => Ok((profile, topic)),
+    }
+}
+
+fn flag_value<'a>(
+    args: &mut Iter<'a, String>,
+    flag: &'static str,
+) -> Result<&'a String, UsageError> {
+    args.next().ok_or(UsageError::MissingValue(flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse_line(line: &str) -> Result<Command, UsageError> {
+        let args: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn commands_with_their_flags() {
+        assert!(matches!(
+            parse_line("local --read-only --tick-rate 100 --no-commit"),
+            Ok(Command::Tui {
+                profile: Some(profile),
+                read_only: true,
+                tick_rate_ms: Some(100),
+                no_commit: true,
+            }) if profile == "local"
+        ));
+        assert!(matches!(
+            parse_line("consume local orders --max 5 --from latest --key-filter user-"),
+            Ok(Command::Consume {
+                max: Some(5),
+                from: FetchOffset::Latest,
+                key_filter: Some(prefix),
+                no_commit: false,
+                ..
+            }) if prefix == "user-"
+        ));
+        assert!(matches!(
+            parse_line("produce local orders --key-separator \\t"),
+            Ok(Command::Produce { key_separator: Some(sep), .. }) if sep == "\t"
+        ));
+        assert!(matches!(
+            parse_line("replay local orders dump.jsonl --rate 10"),
+            Ok(Command::Replay { rate: Some(10), file, .. }) if file == Path::new("dump.jsonl")
+        ));
+        assert!(matches!(
+            parse_line("lag local billing --threshold 0"),
+            Ok(Command::Lag {
+                threshold: Some(0),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn missing_flag_values() {
+        for (line, missing) in [
+            ("local --tick-rate", "--tick-rate"),
+            ("lag local billing --threshold", "--threshold"),
+            ("consume local orders --max", "--max"),
+            ("consume local orders --from", "--from"),
+            ("consume local orders --key-filter", "--key-filter"),
+            ("produce local orders --key-separator", "--key-separator"),
+            ("replay local orders dump.jsonl --rate", "--rate"),
+        ] {
+            assert!(
+                matches!(parse_line(line), Err(UsageError::MissingValue(flag)) if flag == missing),
+                "{}",
+                line
+            );
+        }
+        let mut args = vec!["topics".to_string(), "--config".to_string()];
+        assert!(matches!(
+            take_config(&mut args),
+            Err(UsageError::MissingValue("--config"))
+        ));
+    }
+
+    #[test]
+    fn invalid_flag_values() {
+        for (line, invalid) in [
+            ("local --tick-rate 10", "--tick-rate"),
+            ("local --tick-rate fast", "--tick-rate"),
+            ("lag local billing --threshold -1", "--threshold"),
+            ("consume local orders --max many", "--max"),
+            ("consume local orders --from middle", "--from"),
+            ("replay local orders dump.jsonl --rate 0", "--rate"),
+        ] {
+            assert!(
+                matches!(parse_line(line), Err(UsageError::InvalidValue(flag, _)) if flag == invalid),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn missing_and_extra_arguments() {
+        assert!(matches!(
+            parse_line("topics"),
+            Err(UsageError::MissingProfile)
+        ));
+        assert!(matches!(
+            parse_line("consume local"),
+            Err(UsageError::MissingTopic)
+        ));
+        assert!(matches!(
+            parse_line("lag local"),
+            Err(UsageError::MissingGroup)
+        ));
+        assert!(matches!(
+            parse_line("replay local orders"),
+            Err(UsageError::MissingFile)
+        ));
+        for (line, extra) in [
+            ("init now", "now"),
+            ("local other", "other"),
+            ("topics local other", "other"),
+            ("lag local billing other", "other"),
+            ("consume local orders other", "other"),
+            ("produce local orders other", "other"),
+            ("replay local orders dump.jsonl other", "other"),
+            ("consume local orders --follow", "--follow"),
+        ] {
+            assert!(
+                matches!(parse_line(line), Err(UsageError::Unexpected(arg)) if arg == extra),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn config_is_taken_from_anywhere() {
+        let mut args: Vec<String> = ["topics", "--config", "c.json", "local"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(
+            take_config(&mut args).unwrap(),
+            Some(PathBuf::from("c.json"))
+        );
+        assert_eq!(args, ["topics", "local"]);
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("no clipboard available, install wl-copy, xclip or xsel")]
+    Unavailable,
+    #[error("{0} failed: {1}")]
+    Failed(&'static str, io::Error),
+}
+
+/// Clipboard tools to try in order, with the arguments that make them read
+/// the text to copy from stdin.
+const TOOLS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+];
+
+/// Copies `text` to the system clipboard through the first clipboard tool
+/// found on the PATH. Headless machines usually have none, which is reported
+/// as `Unavailable` rather than treated as a failure of the tool.
+pub fn copy(text: &str) -> Result<(), ClipboardError> {
+    for (tool, args) in TOOLS {
+        let mut child = match Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(ClipboardError::Failed(tool, e)),
+        };
+        let written = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(text.as_bytes());
+        let status = child.wait().map_err(|e| ClipboardError::Failed(tool, e))?;
+        written.map_err(|e| ClipboardError::Failed(tool, e))?;
+        if !status.success() {
+            // xclip exits with an error when no X display is reachable.
+            continue;
+        }
+        return Ok(());
+    }
+    Err(ClipboardError::Unavailable)
+}
@@ -1,11 +1,235 @@
 extern crate dirs;
-use serde::Deserialize;
+use log::{debug, warn};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs;
+use std::io;
+use std::net::Ipv6Addr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tui::style::Color;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not determine your home directory")]
+    NoHomeDir,
+    #[error(
+        "config file not found at {0}, run kafku init to create one \
+         or point KAFKU_CONFIG at an existing file"
+    )]
+    NotFound(PathBuf),
+    #[error("could not read config file {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("config file {0} is not valid JSON: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("profile \"{0}\" is defined more than once in {1}")]
+    DuplicateProfile(String, PathBuf),
+    #[error("profile \"{0}\" is defined in both {1} and {2}")]
+    ConflictingProfile(String, PathBuf, PathBuf),
+    #[error("could not write config file {0}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("profile \"{0}\" sets tick_rate_ms to {1}, which must be between 50 and 1000")]
+    InvalidTickRate(String, u64),
+    #[error(
+        "profile \"{0}\" sets schema_registry to {1}, which is not an http:// or https:// URL"
+    )]
+    InvalidSchemaRegistry(String, String),
+    #[error("profile \"{0}\" in {2} has an unknown setting \"{1}\"")]
+    UnknownField(String, String, PathBuf),
+    #[error("profile \"{0}\" has no brokers")]
+    NoBrokers(String),
+    #[error("broker \"{0}\" is not host:port, such as localhost:9092 or [::1]:9092 for IPv6")]
+    InvalidBroker(String),
+    #[error("profile \"{0}\" must set proto_descriptor and proto_message_type together")]
+    IncompleteProto(String),
+    #[error("profiles {0} are all marked as the default, but only one can be")]
+    MultipleDefaults(String),
+    #[error(
+        "profile \"{0}\" sets sasl_mechanism to {1}, which is not PLAIN, SCRAM-SHA-256 or \
+         SCRAM-SHA-512"
+    )]
+    InvalidSaslMechanism(String, String),
+}
+
+/// Takes precedence over `~/.kafka/.config` as the config file location.
+const CONFIG_ENV: &str = "KAFKU_CONFIG";
+/// Takes precedence over the brokers of the selected profile.
+const BROKER_ENV: &str = "KAFKU_BROKER";
+
+/// The group used when neither the profile nor the OS names one.
+const FALLBACK_GROUP: &str = "kafku";
+
+/// Short enough that a dead broker doesn't leave the UI frozen for long.
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+
+/// How long the producer waits for acknowledgements unless told otherwise.
+const DEFAULT_ACK_TIMEOUT_MS: u64 = 10_000;
+
+/// About five seconds of retrying, 100 ms apart, for the same reason as the
+/// connect timeout.
+const DEFAULT_REQUEST_RETRIES: u32 = 50;
+
+/// How many lines the Messages pane keeps by default.
+const DEFAULT_MAX_MESSAGES: usize = 500;
+
+/// Topics with more messages than this need their name typed to be deleted.
+const DEFAULT_DELETE_CONFIRM_MESSAGES: i64 = 10_000;
+
+/// How often the UI redraws and follows topics by default.
+const DEFAULT_TICK_RATE_MS: u64 = 200;
+
+/// Faster ticks waste CPU and slower ones make the UI feel stuck.
+pub const TICK_RATE_MS: RangeInclusive<u64> = 50..=1000;
+
+/// How many times loading metadata is tried before giving up.
+const DEFAULT_METADATA_ATTEMPTS: u32 = 3;
+
+const TEMPLATE: &str = r#"[
+  {
+    "name": "local",
+    "broker": "localhost:9092"
+  }
+]
+"#;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct KafkaConfig {
     name: String,
-    broker: String,
+    #[serde(default)]
+    default: bool,
+    #[serde(alias = "broker", default, deserialize_with = "one_or_many")]
+    brokers: Vec<String>,
+    group: Option<String>,
+    sasl_username: Option<String>,
+    sasl_password: Option<String>,
+    sasl_mechanism: Option<String>,
+    tls_ca_cert: Option<PathBuf>,
+    tls_client_cert: Option<PathBuf>,
+    tls_client_key: Option<PathBuf>,
+    delete_confirm_messages: Option<i64>,
+    max_messages: Option<usize>,
+    connect_timeout_ms: Option<u64>,
+    request_retries: Option<u32>,
+    metadata_attempts: Option<u32>,
+    tick_rate_ms: Option<u64>,
+    fetch_min_bytes: Option<u32>,
+    fetch_max_bytes: Option<u32>,
+    ack_timeout_ms: Option<u64>,
+    required_acks: Option<Acks>,
+    compression: Option<Compression>,
+    #[serde(default, deserialize_with = "color_name")]
+    accent_color: Option<Color>,
+    #[serde(default)]
+    read_only: bool,
+    schema_registry: Option<String>,
+    proto_descriptor: Option<PathBuf>,
+    proto_message_type: Option<String>,
+    topic_columns: Option<Vec<TopicColumn>>,
+    /// Settings kafku doesn't know, most likely misspelled ones, which are
+    /// logged, and rejected when opening the profile.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_json::Value>,
+    /// The config file the profile was read from.
+    #[serde(skip)]
+    file: PathBuf,
+}
+
+/// Certificate paths for TLS connections, already resolved against the
+/// config directory.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+/// How SASL credentials are exchanged with the brokers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaslMechanism {
+    Plain,
+    ScramSha256,
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    const ALL: [SaslMechanism; 3] = [
+        SaslMechanism::Plain,
+        SaslMechanism::ScramSha256,
+        SaslMechanism::ScramSha512,
+    ];
+
+    /// The name Kafka uses for the mechanism, ignoring case.
+    fn parse(name: &str) -> Option<SaslMechanism> {
+        Self::ALL
+            .into_iter()
+            .find(|mechanism| mechanism.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+/// How many replicas must acknowledge a produced message.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Acks {
+    None,
+    One,
+    All,
+}
+
+/// How produced messages are compressed. gzip and snappy come from default
+/// features of the kafka crate; it has no lz4 support, so `"lz4"` is rejected
+/// when the config is read.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+}
+
+/// A column of the partition table in the Topics view. Unknown names are
+/// rejected when the config is read.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicColumn {
+    Partition,
+    Leader,
+    Available,
+    Replicas,
+    Isr,
+    Offset,
+    Retained,
+    Lag,
+    Size,
+}
+
+/// Settings for producing messages.
+#[derive(Debug, Clone)]
+pub struct ProducerConfig {
+    /// How long to wait for a broker to acknowledge a message.
+    pub ack_timeout: Duration,
+    pub required_acks: Acks,
+    pub compression: Compression,
+}
+
+/// Settings for consuming messages.
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    /// How much data a fetch waits for before returning early.
+    pub fetch_min_bytes: i32,
+    /// The most data a fetch returns per partition, which also bounds the
+    /// largest message that can be read.
+    pub fetch_max_bytes: i32,
 }
 
 impl KafkaConfig {
@@ -13,37 +237,788 @@ impl KafkaConfig {
         &self.name
     }
 
-    pub fn broker(&self) -> &String {
-        &self.broker
+    pub fn broker(&self) -> &Vec<String> {
+        &self.brokers
+    }
+
+    /// The consumer group used when pulling messages, defaulting to the OS
+    /// username so developers don't share committed offsets. A profile
+    /// without `"group"` run by user `alice` commits under group `alice`.
+    pub fn group(&self) -> String {
+        match &self.group {
+            Some(group) if !group.trim().is_empty() => group.clone(),
+            _ => {
+                let user = whoami::username();
+                // Some containers run without a named user.
+                if user.trim().is_empty() {
+                    FALLBACK_GROUP.to_string()
+                } else {
+                    user
+                }
+            }
+        }
+    }
+
+    /// Deleting a topic with more messages than this requires typing its name
+    /// rather than answering a yes/no prompt.
+    pub fn delete_confirm_messages(&self) -> i64 {
+        self.delete_confirm_messages
+            .unwrap_or(DEFAULT_DELETE_CONFIRM_MESSAGES)
+    }
+
+    /// The most lines the Messages pane keeps, and the most messages a single
+    /// Pull takes. Older lines are dropped first.
+    pub fn max_messages(&self) -> usize {
+        self.max_messages.unwrap_or(DEFAULT_MAX_MESSAGES).max(1)
+    }
+
+    /// How long to keep trying to reach a broker before giving up.
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.connect_timeout_ms
+                .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS),
+        )
+    }
+
+    /// How many times the kafka client retries a request to a broker it
+    /// can't reach, 100 ms apart, before giving up.
+    pub fn request_retries(&self) -> u32 {
+        self.request_retries
+            .unwrap_or(DEFAULT_REQUEST_RETRIES)
+            .max(1)
+    }
+
+    /// How many times to try loading metadata and offsets when the failure
+    /// looks transient, such as a dropped connection or a leader election.
+    pub fn metadata_attempts(&self) -> u32 {
+        self.metadata_attempts
+            .unwrap_or(DEFAULT_METADATA_ATTEMPTS)
+            .max(1)
+    }
+
+    /// How often the UI redraws and polls followed topics. Worth raising
+    /// over slow SSH connections, where every redraw is costly.
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_rate_ms.unwrap_or(DEFAULT_TICK_RATE_MS))
     }
 
-    fn clone(&self) -> KafkaConfig {
-        return KafkaConfig {
-            name: self.name.to_string(),
-            broker: self.broker.to_string(),
+    /// Fetch sizes, defaulting to those of the kafka crate: 4 KiB at least
+    /// and 32 KiB at most per partition.
+    pub fn consumer(&self) -> ConsumerConfig {
+        let bytes = |value: Option<u32>, default: i32| {
+            value.map_or(default, |value| value.clamp(1, i32::MAX as u32) as i32)
         };
+        ConsumerConfig {
+            fetch_min_bytes: bytes(self.fetch_min_bytes, kafka::client::DEFAULT_FETCH_MIN_BYTES),
+            fetch_max_bytes: bytes(
+                self.fetch_max_bytes,
+                kafka::client::DEFAULT_FETCH_MAX_BYTES_PER_PARTITION,
+            ),
+        }
+    }
+
+    pub fn producer(&self) -> ProducerConfig {
+        ProducerConfig {
+            ack_timeout: Duration::from_millis(
+                self.ack_timeout_ms.unwrap_or(DEFAULT_ACK_TIMEOUT_MS),
+            ),
+            required_acks: self.required_acks.unwrap_or(Acks::One),
+            compression: self.compression.unwrap_or(Compression::None),
+        }
+    }
+
+    /// Whether the TUI opens this profile when none is named.
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+
+    /// The color of focused borders, selections and hotkeys, yellow unless
+    /// the profile sets `"accent_color"`, e.g. `"red"` for production.
+    pub fn accent_color(&self) -> Color {
+        self.accent_color.unwrap_or(Color::Yellow)
+    }
+
+    /// Whether producing, creating and deleting topics and resetting offsets
+    /// are refused, for looking around production clusters safely.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The columns of the partition table, in order, such as
+    /// `["partition", "offset", "lag"]`. Unset or empty shows all of them.
+    pub fn topic_columns(&self) -> Vec<TopicColumn> {
+        match &self.topic_columns {
+            Some(columns) if !columns.is_empty() => columns.clone(),
+            _ => vec![
+                TopicColumn::Partition,
+                TopicColumn::Leader,
+                TopicColumn::Available,
+                TopicColumn::Isr,
+                TopicColumn::Offset,
+                TopicColumn::Retained,
+                TopicColumn::Lag,
+                TopicColumn::Size,
+            ],
+        }
+    }
+
+    /// The Confluent Schema Registry used to decode Avro messages, such as
+    /// `"http://localhost:8081"`. Only used when built with the `avro` feature.
+    pub fn schema_registry(&self) -> Option<&str> {
+        self.schema_registry.as_deref()
+    }
+
+    /// A compiled `FileDescriptorSet`, as `protoc --descriptor_set_out`
+    /// writes, and the fully qualified message type to decode values as.
+    /// Only used when built with the `protobuf` feature.
+    pub fn proto(&self) -> Option<(&Path, &str)> {
+        self.proto_descriptor
+            .as_deref()
+            .zip(self.proto_message_type.as_deref())
+    }
+
+    pub fn tls(&self) -> Option<TlsConfig> {
+        if self.tls_ca_cert.is_none()
+            && self.tls_client_cert.is_none()
+            && self.tls_client_key.is_none()
+        {
+            return None;
+        }
+        Some(TlsConfig {
+            ca_cert: self.tls_ca_cert.clone(),
+            client_cert: self.tls_client_cert.clone(),
+            client_key: self.tls_client_key.clone(),
+        })
+    }
+
+    /// Makes relative certificate and descriptor paths relative to `dir`
+    /// rather than the current working directory.
+    fn resolve_paths(&mut self, dir: &Path) {
+        let paths = [
+            &mut self.tls_ca_cert,
+            &mut self.tls_client_cert,
+            &mut self.tls_client_key,
+            &mut self.proto_descriptor,
+        ];
+        for path in paths.into_iter().flatten() {
+            if path.is_relative() {
+                *path = dir.join(&path);
+            }
+        }
     }
+
+    /// The SASL mechanism, PLAIN unless the profile names another. Names
+    /// were checked when the config was read.
+    pub fn sasl_mechanism(&self) -> SaslMechanism {
+        self.sasl_mechanism
+            .as_deref()
+            .and_then(SaslMechanism::parse)
+            .unwrap_or(SaslMechanism::Plain)
+    }
+
+    pub fn uses_sasl(&self) -> bool {
+        self.sasl_username.is_some()
+            || self.sasl_password.is_some()
+            || self.sasl_mechanism.is_some()
+    }
+}
+
+/// What the TUI remembers between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    pub profile: Option<String>,
+    pub topic: Option<String>,
 }
 
-pub fn get(key: String) -> Option<KafkaConfig> {
-    return match dirs::home_dir() {
-        Some(path) => {
-            let file_path = format!("{}/.kafka/.config", path.display());
-            println!("Reading config file: {}", file_path);
-            let contents = fs::read_to_string(file_path)
-                .expect("Something went wrong reading the file");
-            let configs: Vec<KafkaConfig> =
-                serde_json::from_str(&contents).expect("JSON was not well-formatted");
-            let result: Vec<&KafkaConfig> = configs.iter().filter(|c| c.name == key).collect();
-            if result.len() != 0 {
-                Some(result[0].clone())
-            } else {
-                None
+/// Accepts either a single broker string or a list of brokers, so existing
+/// configs using `"broker": "host:port"` keep working.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(broker) => vec![broker],
+        OneOrMany::Many(brokers) => brokers,
+    })
+}
+
+/// Reads a color by name, such as `"red"` or `"lightblue"`, or as `"#rrggbb"`.
+fn color_name<'de, D>(deserializer: D) -> Result<Option<Color>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    let color = match name.to_lowercase().replace(['_', '-', ' '], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex => match hex
+            .strip_prefix('#')
+            .map(|hex| u32::from_str_radix(hex, 16))
+        {
+            Some(Ok(rgb)) if hex.len() == 7 => {
+                Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            }
+            _ => {
+                return Err(de::Error::custom(format!(
+                    "unknown color \"{}\", expected a name like \"red\" or \"#rrggbb\"",
+                    name
+                )))
             }
+        },
+    };
+    Ok(Some(color))
+}
+
+pub fn config_path() -> Result<PathBuf, ConfigError> {
+    match config_env_path() {
+        Some(path) => Ok(path),
+        None => Ok(kafka_dir()?.join(".config")),
+    }
+}
+
+/// The file `KAFKU_CONFIG` names, if it is set.
+fn config_env_path() -> Option<PathBuf> {
+    env::var_os(CONFIG_ENV)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `~/.kafka`, where kafku keeps its files.
+pub fn kafka_dir() -> Result<PathBuf, ConfigError> {
+    dirs::home_dir()
+        .map(|home| home.join(".kafka"))
+        .ok_or(ConfigError::NoHomeDir)
+}
+
+fn state_path() -> Result<PathBuf, ConfigError> {
+    Ok(kafka_dir()?.join(".state"))
+}
+
+/// Loads the remembered state. A missing or unreadable state file just
+/// means nothing is remembered.
+pub fn load_state() -> State {
+    state_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_state(state: &State) -> Result<(), ConfigError> {
+    let path = state_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| ConfigError::Write(dir.to_path_buf(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(state).expect("state serializes to JSON");
+    fs::write(&path, contents).map_err(|e| ConfigError::Write(path, e))
+}
+
+/// Writes an example config with a single `local` profile to `path`, creating
+/// the parent directory if needed. Existing files are overwritten.
+pub fn write_template(path: &Path) -> Result<(), ConfigError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| ConfigError::Write(dir.to_path_buf(), e))?;
+    }
+    fs::write(path, TEMPLATE).map_err(|e| ConfigError::Write(path.to_path_buf(), e))
+}
+
+/// Reads every profile from `~/.kafka/.config` and `~/.kafka/config.d`, or
+/// only from the file `KAFKU_CONFIG` names when it is set.
+pub fn load_all() -> Result<Vec<KafkaConfig>, ConfigError> {
+    match config_env_path() {
+        Some(path) => load_all_from(&path),
+        None => load_all_with(&config_path()?, included_files()?),
+    }
+}
+
+/// Reads every profile from `file_path` alone, which must exist. A config
+/// chosen by path doesn't pick up `~/.kafka/config.d`.
+pub fn load_all_from(file_path: &Path) -> Result<Vec<KafkaConfig>, ConfigError> {
+    load_all_with(file_path, vec![])
+}
+
+/// Reads every profile from `file_path` and the `included` files, rejecting
+/// profiles that share a name. Either source may be missing, but not both.
+fn load_all_with(
+    file_path: &Path,
+    included: Vec<PathBuf>,
+) -> Result<Vec<KafkaConfig>, ConfigError> {
+    let mut sources = vec![];
+    match load_file(file_path) {
+        Ok(configs) => sources.push((file_path.to_path_buf(), configs)),
+        Err(ConfigError::NotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    for path in included {
+        let configs = load_file(&path)?;
+        sources.push((path, configs));
+    }
+    if sources.is_empty() {
+        return Err(ConfigError::NotFound(file_path.to_path_buf()));
+    }
+    validate(&sources, broker_override().is_some())?;
+    Ok(sources
+        .into_iter()
+        .flat_map(|(_, configs)| configs)
+        .collect())
+}
+
+/// The `*.json` files in `~/.kafka/config.d`, in name order so that the
+/// profile list is the same on every run.
+fn included_files() -> Result<Vec<PathBuf>, ConfigError> {
+    let dir = match kafka_dir() {
+        Ok(dir) => dir.join("config.d"),
+        // KAFKU_CONFIG works without a home directory.
+        Err(_) => return Ok(vec![]),
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(ConfigError::Read(dir, e)),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry.map_err(|e| ConfigError::Read(dir.clone(), e))?.path();
+        if path.extension().is_some_and(|ext| ext == "json") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn load_file(file_path: &Path) -> Result<Vec<KafkaConfig>, ConfigError> {
+    debug!("reading config file {}", file_path.display());
+    let contents = fs::read_to_string(file_path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ConfigError::NotFound(file_path.to_path_buf()),
+        _ => ConfigError::Read(file_path.to_path_buf(), e),
+    })?;
+    let mut configs: Vec<KafkaConfig> = serde_json::from_str(&contents)
+        .map_err(|e| ConfigError::Parse(file_path.to_path_buf(), e))?;
+    for config in &mut configs {
+        config.file = file_path.to_path_buf();
+        if let Some(dir) = file_path.parent() {
+            config.resolve_paths(dir);
+        }
+    }
+    Ok(configs)
+}
+
+/// Checks every profile in `sources`. With `brokers_overridden`, the
+/// profiles' own brokers go unused, so they aren't checked.
+fn validate(
+    sources: &[(PathBuf, Vec<KafkaConfig>)],
+    brokers_overridden: bool,
+) -> Result<(), ConfigError> {
+    let mut seen: HashMap<&str, &PathBuf> = HashMap::new();
+    for (path, configs) in sources {
+        for config in configs {
+            // Only the profile being opened has to be free of them.
+            for field in config.unknown.keys() {
+                warn!(
+                    "profile \"{}\" in {} has an unknown setting \"{}\"",
+                    config.name,
+                    path.display(),
+                    field
+                );
+            }
+            if !brokers_overridden {
+                if config.brokers.iter().all(|b| b.trim().is_empty()) {
+                    return Err(ConfigError::NoBrokers(config.name.clone()));
+                }
+                if let Some(broker) = config.brokers.iter().find(|b| !is_host_and_port(b)) {
+                    return Err(ConfigError::InvalidBroker(broker.clone()));
+                }
+            }
+            if let Some(ms) = config.tick_rate_ms.filter(|ms| !TICK_RATE_MS.contains(ms)) {
+                return Err(ConfigError::InvalidTickRate(config.name.clone(), ms));
+            }
+            if let Some(url) = config
+                .schema_registry
+                .as_ref()
+                .filter(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+            {
+                return Err(ConfigError::InvalidSchemaRegistry(
+                    config.name.clone(),
+                    url.clone(),
+                ));
+            }
+            if let Some(name) = config
+                .sasl_mechanism
+                .as_ref()
+                .filter(|name| SaslMechanism::parse(name).is_none())
+            {
+                return Err(ConfigError::InvalidSaslMechanism(
+                    config.name.clone(),
+                    name.clone(),
+                ));
+            }
+            if config.proto_descriptor.is_some() != config.proto_message_type.is_some() {
+                return Err(ConfigError::IncompleteProto(config.name.clone()));
+            }
+            match seen.insert(config.name.as_str(), path) {
+                Some(first) if first == path => {
+                    return Err(ConfigError::DuplicateProfile(
+                        config.name.clone(),
+                        path.clone(),
+                    ))
+                }
+                Some(first) => {
+                    return Err(ConfigError::ConflictingProfile(
+                        config.name.clone(),
+                        first.clone(),
+                        path.clone(),
+                    ))
+                }
+                None => {}
+            }
+        }
+    }
+    let defaults: Vec<_> = sources
+        .iter()
+        .flat_map(|(_, configs)| configs)
+        .filter(|config| config.default)
+        .map(|config| config.name.as_str())
+        .collect();
+    if defaults.len() > 1 {
+        return Err(ConfigError::MultipleDefaults(defaults.join(", ")));
+    }
+    Ok(())
+}
+
+/// The names of the profiles `load_all` reads, in the order they are defined.
+pub fn list_profile_names() -> Result<Vec<String>, ConfigError> {
+    Ok(load_all()?.into_iter().map(|c| c.name).collect())
+}
+
+/// The names of the profiles in `path`, in the order they are defined.
+pub fn list_profile_names_from(path: &Path) -> Result<Vec<String>, ConfigError> {
+    Ok(load_all_from(path)?.into_iter().map(|c| c.name).collect())
+}
+
+/// Loads the profile named `key` from the profiles `load_all` reads, with
+/// its brokers replaced by those in `KAFKU_BROKER` when that is set.
+pub fn get(key: String) -> Result<Option<KafkaConfig>, ConfigError> {
+    let config = find(load_all()?, &key)?;
+    with_broker_override(config, broker_override())
+}
+
+/// Loads the profile named `key` from `path` alone, with its brokers
+/// replaced by those in `KAFKU_BROKER` when that is set.
+pub fn get_from(path: &Path, key: String) -> Result<Option<KafkaConfig>, ConfigError> {
+    let config = find(load_all_from(path)?, &key)?;
+    with_broker_override(config, broker_override())
+}
+
+/// The profile named `key` in `configs`, which fails on settings kafku
+/// doesn't know, since they're likely typos for ones it does.
+fn find(configs: Vec<KafkaConfig>, key: &str) -> Result<Option<KafkaConfig>, ConfigError> {
+    let config = configs.into_iter().find(|c| c.name == key);
+    if let Some(config) = &config {
+        if let Some(field) = config.unknown.keys().next() {
+            return Err(ConfigError::UnknownField(
+                config.name.clone(),
+                field.clone(),
+                config.file.clone(),
+            ));
+        }
+    }
+    Ok(config)
+}
+
+/// `config` with its brokers replaced by `brokers`, the `KAFKU_BROKER`
+/// override, when there is one.
+fn with_broker_override(
+    config: Option<KafkaConfig>,
+    brokers: Option<Vec<String>>,
+) -> Result<Option<KafkaConfig>, ConfigError> {
+    if let Some(broker) = brokers.iter().flatten().find(|b| !is_host_and_port(b)) {
+        return Err(ConfigError::InvalidBroker(broker.clone()));
+    }
+    Ok(config.map(|mut config| {
+        if let Some(brokers) = brokers {
+            config.brokers = brokers;
         }
+        config
+    }))
+}
+
+/// Whether `broker` is a host name or IPv4 address and a port, or an IPv6
+/// address in brackets and a port, such as `[::1]:9092`.
+fn is_host_and_port(broker: &str) -> bool {
+    let (host, port) = match broker.trim().rsplit_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let valid_host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(ipv6) => ipv6.parse::<Ipv6Addr>().is_ok(),
         None => {
-            println!("Impossible to get your home dir!");
-            None
+            !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        }
+    };
+    valid_host && port.parse::<u16>().is_ok_and(|port| port > 0)
+}
+
+/// The comma separated brokers in `KAFKU_BROKER`, if it is set.
+pub fn broker_override() -> Option<Vec<String>> {
+    broker_list(&env::var(BROKER_ENV).ok()?)
+}
+
+/// The brokers in a comma separated `list`, or None when it names none.
+fn broker_list(list: &str) -> Option<Vec<String>> {
+    let brokers: Vec<String> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|broker| !broker.is_empty())
+        .map(str::to_string)
+        .collect();
+    if brokers.is_empty() {
+        None
+    } else {
+        Some(brokers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kafku-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes `json` as the config file of a fresh directory.
+    fn config_file(name: &str, json: &str) -> PathBuf {
+        let path = temp_dir(name).join(".config");
+        fs::write(&path, json).unwrap();
+        path
+    }
+
+    fn profile(json: &str) -> KafkaConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn missing_config_file_is_not_found() {
+        let path = temp_dir("missing").join(".config");
+        match load_all_with(&path, vec![]) {
+            Err(ConfigError::NotFound(missing)) => assert_eq!(missing, path),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_explicit_config_file_must_exist() {
+        let dir = temp_dir("explicit");
+        let included = dir.join("extra.json");
+        fs::write(
+            &included,
+            r#"[{"name": "extra", "broker": "localhost:9092"}]"#,
+        )
+        .unwrap();
+        let path = dir.join(".config");
+        // Only the default config may be missing while config.d has profiles.
+        assert_eq!(load_all_with(&path, vec![included]).unwrap().len(), 1);
+        assert!(matches!(
+            load_all_from(&path),
+            Err(ConfigError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_profile_names_are_rejected() {
+        let path = config_file(
+            "duplicate",
+            r#"[
+                {"name": "local", "broker": "localhost:9092"},
+                {"name": "local", "broker": "localhost:9093"}
+            ]"#,
+        );
+        match load_all_with(&path, vec![]) {
+            Err(ConfigError::DuplicateProfile(name, file)) => {
+                assert_eq!(name, "local");
+                assert_eq!(file, path);
+            }
+            other => panic!("expected DuplicateProfile, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_settings_fail_only_the_opened_profile() {
+        let path = config_file(
+            "unknown",
+            r#"[
+                {"name": "local", "broker": "localhost:9092", "grup": "orders"},
+                {"name": "staging", "broker": "kafka:9092"}
+            ]"#,
+        );
+        let configs = load_all_with(&path, vec![]).unwrap();
+        assert!(find(configs.clone(), "staging").unwrap().is_some());
+        match find(configs, "local") {
+            Err(ConfigError::UnknownField(name, field, file)) => {
+                assert_eq!(name, "local");
+                assert_eq!(field, "grup");
+                assert_eq!(file, path);
+            }
+            other => panic!("expected UnknownField, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn profiles_need_brokers() {
+        let path = config_file(
+            "no-brokers",
+            r#"[{"name": "local", "brokr": "localhost:9092"}]"#,
+        );
+        match load_all_with(&path, vec![]) {
+            Err(ConfigError::NoBrokers(name)) => assert_eq!(name, "local"),
+            other => panic!("expected NoBrokers, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn group_defaults_to_the_os_user() {
+        let user = whoami::username();
+        let expected = if user.trim().is_empty() {
+            FALLBACK_GROUP.to_string()
+        } else {
+            user
+        };
+        let without = profile(r#"{"name": "local", "broker": "localhost:9092"}"#);
+        assert_eq!(without.group(), expected);
+        let blank = profile(r#"{"name": "local", "broker": "localhost:9092", "group": " "}"#);
+        assert_eq!(blank.group(), expected);
+        let named = profile(r#"{"name": "local", "broker": "localhost:9092", "group": "orders"}"#);
+        assert_eq!(named.group(), "orders");
+    }
+
+    #[test]
+    fn timeouts_and_retries_are_set_separately() {
+        let defaults = profile(r#"{"name": "local", "broker": "localhost:9092"}"#);
+        assert_eq!(defaults.connect_timeout(), Duration::from_secs(5));
+        assert_eq!(defaults.producer().ack_timeout, Duration::from_secs(10));
+        assert_eq!(defaults.request_retries(), DEFAULT_REQUEST_RETRIES);
+        let set = profile(
+            r#"{"name": "local", "broker": "localhost:9092", "connect_timeout_ms": 1000,
+                "ack_timeout_ms": 30000, "request_retries": 3}"#,
+        );
+        assert_eq!(set.connect_timeout(), Duration::from_secs(1));
+        assert_eq!(set.producer().ack_timeout, Duration::from_secs(30));
+        assert_eq!(set.request_retries(), 3);
+    }
+
+    #[test]
+    fn broker_env_overrides_the_profile() {
+        let local = || Some(profile(r#"{"name": "local", "broker": "localhost:9092"}"#));
+        let single = with_broker_override(local(), broker_list("kafka-1:9092"));
+        assert_eq!(single.unwrap().unwrap().broker(), &["kafka-1:9092"]);
+        let list = with_broker_override(local(), broker_list("kafka-1:9092, kafka-2:9092,"));
+        assert_eq!(
+            list.unwrap().unwrap().broker(),
+            &["kafka-1:9092", "kafka-2:9092"]
+        );
+        let invalid = with_broker_override(local(), broker_list("kafka-1"));
+        assert!(matches!(invalid, Err(ConfigError::InvalidBroker(b)) if b == "kafka-1"));
+        assert_eq!(broker_list(" , "), None);
+        let unset = with_broker_override(local(), None).unwrap().unwrap();
+        assert_eq!(unset.broker(), &["localhost:9092"]);
+    }
+
+    #[test]
+    fn brokers_must_be_host_and_port() {
+        assert!(is_host_and_port("10.0.0.1:9092"));
+        assert!(is_host_and_port("[::1]:9092"));
+        assert!(is_host_and_port("[fe80::1ff:fe23:4567:890a]:9093"));
+        assert!(is_host_and_port("kafka-1.internal:9092"));
+        assert!(is_host_and_port(" localhost:9092 "));
+
+        assert!(!is_host_and_port("localhost"));
+        assert!(!is_host_and_port("localhost:"));
+        assert!(!is_host_and_port("::1:9092"));
+        assert!(!is_host_and_port("[::1]"));
+        assert!(!is_host_and_port("localhost:kafka"));
+        assert!(!is_host_and_port("localhost:0"));
+        assert!(!is_host_and_port("localhost:65536"));
+        assert!(!is_host_and_port(":9092"));
+        assert!(!is_host_and_port("[]:9092"));
+    }
+
+    #[test]
+    fn overridden_brokers_are_not_checked() {
+        let path = PathBuf::from(".config");
+        let sources = vec![(
+            path,
+            vec![profile(r#"{"name": "local", "broker": "kafka"}"#)],
+        )];
+        assert!(matches!(
+            validate(&sources, false),
+            Err(ConfigError::InvalidBroker(b)) if b == "kafka"
+        ));
+        assert!(validate(&sources, true).is_ok());
+    }
+
+    #[test]
+    fn sasl_mechanisms() {
+        assert_eq!(SaslMechanism::parse("PLAIN"), Some(SaslMechanism::Plain));
+        assert_eq!(
+            SaslMechanism::parse("SCRAM-SHA-256"),
+            Some(SaslMechanism::ScramSha256)
+        );
+        assert_eq!(
+            SaslMechanism::parse(" scram-sha-512"),
+            Some(SaslMechanism::ScramSha512)
+        );
+        assert_eq!(
+            SaslMechanism::parse("Scram-Sha-256"),
+            Some(SaslMechanism::ScramSha256)
+        );
+        assert_eq!(SaslMechanism::parse("GSSAPI"), None);
+        assert_eq!(SaslMechanism::parse("SCRAM-SHA-1"), None);
+
+        let path = config_file(
+            "sasl",
+            r#"[{"name": "secure", "broker": "kafka:9093", "sasl_mechanism": "OAUTHBEARER"}]"#,
+        );
+        match load_all_with(&path, vec![]) {
+            Err(ConfigError::InvalidSaslMechanism(profile, mechanism)) => {
+                assert_eq!(profile, "secure");
+                assert_eq!(mechanism, "OAUTHBEARER");
+            }
+            other => panic!("expected InvalidSaslMechanism, got {:?}", other),
         }
+        let valid = profile(
+            r#"{"name": "secure", "broker": "kafka:9093", "sasl_mechanism": "scram-sha-512"}"#,
+        );
+        assert_eq!(valid.sasl_mechanism(), SaslMechanism::ScramSha512);
+        let unset = profile(r#"{"name": "secure", "broker": "kafka:9093"}"#);
+        assert_eq!(unset.sasl_mechanism(), SaslMechanism::Plain);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1173 @@
+//! Turns message values into text for display. Built with the `avro` feature,
+//! values in the Confluent wire format (a zero magic byte, a four-byte schema
+//! id and the Avro-encoded payload) are decoded into JSON, using the schema
+//! the profile's schema registry has under that id. Built with the
+//! `protobuf` feature, values are decoded into JSON as the profile's protobuf
+//! message type. Everything else is shown as UTF-8, with invalid bytes
+//! escaped.
+use crate::kafka_client::parse_message;
+use std::path::Path;
+
+pub struct Decoder {
+    #[cfg(feature = "avro")]
+    registry: Option<avro::Registry>,
+    #[cfg(feature = "protobuf")]
+    proto: Option<proto::Schema>,
+}
+
+impl Decoder {
+    /// A decoder using `schema_registry` for Avro values and `proto`, a
+    /// descriptor set and message type, for protobuf ones. A descriptor set
+    /// that can't be loaded is logged, and its values are shown as text.
+    pub fn new(schema_registry: Option<&str>, proto: Option<(&Path, &str)>) -> Decoder {
+        #[cfg(not(feature = "avro"))]
+        if let Some(url) = schema_registry {
+            log::warn!(
+                "ignoring schema registry {}: kafku was built without the avro feature",
+                url
+            );
+        }
+        #[cfg(not(feature = "protobuf"))]
+        if let Some((path, _)) = proto {
+            log::warn!(
+                "ignoring proto descriptor {}: kafku was built without the protobuf feature",
+                path.display()
+            );
+        }
+        Decoder {
+            #[cfg(feature = "avro")]
+            registry: schema_registry.map(avro::Registry::new),
+            #[cfg(feature = "protobuf")]
+            proto: proto.and_then(|(path, message_type)| {
+                proto::Schema::load(path, message_type)
+                    .map_err(|e| log::warn!("not decoding protobuf: {}", e))
+                    .ok()
+            }),
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        #[cfg(feature = "avro")]
+        if let Some(decoded) = self.registry.as_ref().and_then(|r| r.decode(bytes)) {
+            return decoded;
+        }
+        #[cfg(feature = "protobuf")]
+        if let Some(decoded) = self.proto.as_ref().and_then(|s| s.decode(bytes)) {
+            return decoded;
+        }
+        parse_message(bytes)
+    }
+}
+
+#[cfg(feature = "avro")]
+mod avro {
+    use crate::kafka_client::parse_message;
+    use log::{debug, warn};
+    use openssl::ssl::{SslConnector, SslMethod};
+    use serde::Deserialize;
+    use serde_json::{Map, Number, Value};
+    use std::collections::HashMap;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::str;
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Duration;
+    use thiserror::Error;
+
+    const MAGIC_BYTE: u8 = 0;
+    const REGISTRY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Debug, Error)]
+    enum DecodeError {
+        #[error(transparent)]
+        Io(#[from] io::Error),
+        #[error("TLS handshake with {0} failed: {1}")]
+        Tls(String, String),
+        #[error("schema registry answered {0}")]
+        Status(String),
+        #[error("malformed response from the schema registry")]
+        Malformed,
+        #[error("unsupported schema: {0}")]
+        Schema(String),
+        #[error("payload doesn't match its schema")]
+        Payload,
+    }
+
+    /// A schema registry and the schemas fetched from it so far. Schemas are
+    /// fetched once per id; ids that couldn't be fetched aren't tried again.
+    pub struct Registry {
+        url: String,
+        schemas: Mutex<HashMap<u32, Option<Arc<Schema>>>>,
+    }
+
+    impl Registry {
+        pub fn new(url: &str) -> Registry {
+            Registry {
+                url: url.to_string(),
+                schemas: Mutex::new(HashMap::new()),
+            }
+        }
+
+        /// The value as JSON, or None if it isn't in the wire format or
+        /// doesn't decode with its schema.
+        pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+            let (id, payload) = match bytes {
+                [MAGIC_BYTE, a, b, c, d, payload @ ..] => {
+                    (u32::from_be_bytes([*a, *b, *c, *d]), payload)
+                }
+                _ => return None,
+            };
+            let schema = self.schema(id)?;
+            let mut reader = Reader { bytes: payload };
+            match reader.value(&schema) {
+                Ok(value) if reader.bytes.is_empty() => Some(value.to_string()),
+                Ok(_) | Err(_) => {
+                    debug!("message does not decode with schema {}", id);
+                    None
+                }
+            }
+        }
+
+        fn schema(&self, id: u32) -> Option<Arc<Schema>> {
+            let mut schemas = self.schemas.lock().unwrap_or_else(PoisonError::into_inner);
+            schemas
+                .entry(id)
+                .or_insert_with(|| match self.fetch(id) {
+                    Ok(schema) => Some(Arc::new(schema)),
+                    Err(e) => {
+                        warn!("could not load schema {} from {}: {}", id, self.url, e);
+                        None
+                    }
+                })
+                .clone()
+        }
+
+        fn fetch(&self, id: u32) -> Result<Schema, DecodeError> {
+            let (tls, rest) = match self.url.split_once("://") {
+                Some(("https", rest)) => (true, rest),
+                Some((_, rest)) => (false, rest),
+                None => (false, self.url.as_str()),
+            };
+            let (authority, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            let prefix = prefix.trim_end_matches('/');
+            let path = match prefix {
+                "" => format!("/schemas/ids/{}", id),
+                prefix => format!("/{}/schemas/ids/{}", prefix, id),
+            };
+            let host = authority
+                .rsplit_once(':')
+                .map_or(authority, |(host, _)| host);
+            let address = match authority.contains(':') {
+                true => authority.to_string(),
+                false => format!("{}:{}", authority, if tls { 443 } else { 80 }),
+            };
+            debug!("fetching schema {} from {}", id, self.url);
+
+            let addr = address
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, address.clone()))?;
+            let tcp = TcpStream::connect_timeout(&addr, REGISTRY_TIMEOUT)?;
+            tcp.set_read_timeout(Some(REGISTRY_TIMEOUT))?;
+            tcp.set_write_timeout(Some(REGISTRY_TIMEOUT))?;
+            // HTTP/1.0 keeps the body unchunked and ends it by closing.
+            let request = format!(
+                "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/vnd.schemaregistry.v1+json, \
+                 application/json\r\nUser-Agent: kafku\r\n\r\n",
+                path, authority
+            );
+            let response = if tls {
+                let tls_error = |e: String| DecodeError::Tls(host.to_string(), e);
+                let connector = SslConnector::builder(SslMethod::tls())
+                    .map_err(|e| tls_error(e.to_string()))?
+                    .build();
+                let stream = connector
+                    .connect(host, tcp)
+                    .map_err(|e| tls_error(e.to_string()))?;
+                exchange(stream, &request)?
+            } else {
+                exchange(tcp, &request)?
+            };
+            schema_from_response(&response)
+        }
+    }
+
+    /// The schema in the registry's HTTP `response` to a request by id.
+    fn schema_from_response(response: &[u8]) -> Result<Schema, DecodeError> {
+        #[derive(Deserialize)]
+        struct SchemaResponse {
+            schema: String,
+        }
+
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or(DecodeError::Malformed)?;
+        let head = str::from_utf8(&response[..end]).map_err(|_| DecodeError::Malformed)?;
+        let status = head.lines().next().and_then(|line| line.split_once(' '));
+        match status {
+            Some((_, status)) if status.starts_with("200") => {}
+            Some((_, status)) => return Err(DecodeError::Status(status.to_string())),
+            None => return Err(DecodeError::Malformed),
+        }
+        let body: SchemaResponse =
+            serde_json::from_slice(&response[end + 4..]).map_err(|_| DecodeError::Malformed)?;
+        let json: Value = serde_json::from_str(&body.schema).map_err(|_| DecodeError::Malformed)?;
+        Parser::default().parse(&json, None)
+    }
+
+    fn exchange<S: Read + Write>(mut stream: S, request: &str) -> io::Result<Vec<u8>> {
+        stream.write_all(request.as_bytes())?;
+        let mut response = vec![];
+        // Servers often close TLS connections without a close_notify, which
+        // reads as an error after the whole response arrived.
+        if let Err(e) = stream.read_to_end(&mut response) {
+            if response.is_empty() {
+                return Err(e);
+            }
+        }
+        Ok(response)
+    }
+
+    /// An Avro schema, with named types resolved. Logical types decode as
+    /// their underlying type.
+    #[derive(Debug, Clone)]
+    enum Schema {
+        Null,
+        Boolean,
+        Int,
+        Long,
+        Float,
+        Double,
+        Bytes,
+        String,
+        Record(Vec<(String, Schema)>),
+        Enum(Vec<String>),
+        Array(Box<Schema>),
+        Map(Box<Schema>),
+        Union(Vec<Schema>),
+        Fixed(usize),
+    }
+
+    /// Reads schemas from their JSON form. Named types are copied into each
+    /// place that refers to them, so recursive types aren't supported.
+    #[derive(Default)]
+    struct Parser {
+        named: HashMap<String, Schema>,
+    }
+
+    impl Parser {
+        fn parse(&mut self, json: &Value, namespace: Option<&str>) -> Result<Schema, DecodeError> {
+            let object = match json {
+                Value::String(name) => return self.type_named(name, namespace),
+                Value::Array(branches) => {
+                    return Ok(Schema::Union(
+                        branches
+                            .iter()
+                            .map(|branch| self.parse(branch, namespace))
+                            .collect::<Result<_, _>>()?,
+                    ))
+                }
+                Value::Object(object) => object,
+                other => return Err(DecodeError::Schema(other.to_string())),
+            };
+            let kind = match object.get("type") {
+                Some(Value::String(kind)) => kind.as_str(),
+                Some(nested) => return self.parse(nested, namespace),
+                None => return Err(DecodeError::Schema(json.to_string())),
+            };
+            let invalid = || DecodeError::Schema(json.to_string());
+            let schema = match kind {
+                "array" => Schema::Array(Box::new(
+                    self.parse(object.get("items").ok_or_else(invalid)?, namespace)?,
+                )),
+                "map" => Schema::Map(Box::new(
+                    self.parse(object.get("values").ok_or_else(invalid)?, namespace)?,
+                )),
+                "record" | "error" | "enum" | "fixed" => {
+                    let name = object
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .ok_or_else(invalid)?;
+                    let (fullname, namespace) = match name.rsplit_once('.') {
+                        Some((namespace, _)) => (name.to_string(), Some(namespace.to_string())),
+                        None => {
+                            let namespace = object
+                                .get("namespace")
+                                .and_then(Value::as_str)
+                                .or(namespace)
+                                .filter(|namespace| !namespace.is_empty());
+                            match namespace {
+                                Some(namespace) => (
+                                    format!("{}.{}", namespace, name),
+                                    Some(namespace.to_string()),
+                                ),
+                                None => (name.to_string(), None),
+                            }
+                        }
+                    };
+                    let schema = match kind {
+                        "enum" => Schema::Enum(
+                            object
+                                .get("symbols")
+                                .and_then(Value::as_array)
+                                .ok_or_else(invalid)?
+                                .iter()
+                                .map(|s| s.as_str().map(str::to_string).ok_or_else(invalid))
+                                .collect::<Result<_, _>>()?,
+                        ),
+                        "fixed" => Schema::Fixed(
+                            object
+                                .get("size")
+                                .and_then(Value::as_u64)
+                                .ok_or_else(invalid)? as usize,
+                        ),
+                        _ => Schema::Record(
+                            object
+                                .get("fields")
+                                .and_then(Value::as_array)
+                                .ok_or_else(invalid)?
+                                .iter()
+                                .map(|field| {
+                                    let name = field
+                                        .get("name")
+                                        .and_then(Value::as_str)
+                                        .ok_or_else(invalid)?;
+                                    let schema = self.parse(
+                                        field.get("type").ok_or_else(invalid)?,
+                                        namespace.as_deref(),
+                                    )?;
+                                    Ok((name.to_string(), schema))
+                                })
+                                .collect::<Result<_, DecodeError>>()?,
+                        ),
+                    };
+                    self.named.insert(fullname, schema.clone());
+                    schema
+                }
+                // Primitives with attributes, such as logical types.
+                primitive => self.type_named(primitive, namespace)?,
+            };
+            Ok(schema)
+        }
+
+        fn type_named(&self, name: &str, namespace: Option<&str>) -> Result<Schema, DecodeError> {
+            Ok(match name {
+                "null" => Schema::Null,
+                "boolean" => Schema::Boolean,
+                "int" => Schema::Int,
+                "long" => Schema::Long,
+                "float" => Schema::Float,
+                "double" => Schema::Double,
+                "bytes" => Schema::Bytes,
+                "string" => Schema::String,
+                name => namespace
+                    .and_then(|namespace| self.named.get(&format!("{}.{}", namespace, name)))
+                    .or_else(|| self.named.get(name))
+                    .cloned()
+                    .ok_or_else(|| DecodeError::Schema(format!("unknown type {}", name)))?,
+            })
+        }
+    }
+
+    /// Reads Avro's binary encoding.
+    struct Reader<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+            if n > self.bytes.len() {
+                return Err(DecodeError::Payload);
+            }
+            let (taken, rest) = self.bytes.split_at(n);
+            self.bytes = rest;
+            Ok(taken)
+        }
+
+        /// A zigzag-encoded variable-length integer, which ints use too.
+        fn long(&mut self) -> Result<i64, DecodeError> {
+            let mut value: u64 = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.take(1)?[0];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+                }
+            }
+            Err(DecodeError::Payload)
+        }
+
+        fn len(&mut self) -> Result<usize, DecodeError> {
+            usize::try_from(self.long()?).map_err(|_| DecodeError::Payload)
+        }
+
+        /// Reads the blocks of an array or map, calling `item` for each entry.
+        fn blocks(
+            &mut self,
+            mut item: impl FnMut(&mut Self) -> Result<(), DecodeError>,
+        ) -> Result<(), DecodeError> {
+            loop {
+                let count = match self.long()? {
+                    0 => return Ok(()),
+                    // A negative count is followed by the block's size in bytes.
+                    count if count < 0 => {
+                        self.long()?;
+                        count.unsigned_abs()
+                    }
+                    count => count as u64,
+                };
+                // Guards against garbage claiming billions of empty entries.
+                if count > self.bytes.len() as u64 {
+                    return Err(DecodeError::Payload);
+                }
+                for _ in 0..count {
+                    item(self)?;
+                }
+            }
+        }
+
+        fn value(&mut self, schema: &Schema) -> Result<Value, DecodeError> {
+            Ok(match schema {
+                Schema::Null => Value::Null,
+                Schema::Boolean => Value::Bool(self.take(1)?[0] != 0),
+                Schema::Int | Schema::Long => Value::from(self.long()?),
+                Schema::Float => {
+                    let bytes = self.take(4)?.try_into().map_err(|_| DecodeError::Payload)?;
+                    number(f32::from_le_bytes(bytes).into())
+                }
+                Schema::Double => {
+                    let bytes = self.take(8)?.try_into().map_err(|_| DecodeError::Payload)?;
+                    number(f64::from_le_bytes(bytes))
+                }
+                Schema::Bytes => {
+                    let len = self.len()?;
+                    Value::String(parse_message(self.take(len)?))
+                }
+                Schema::String => {
+                    let len = self.len()?;
+                    let text = str::from_utf8(self.take(len)?).map_err(|_| DecodeError::Payload)?;
+                    Value::String(text.to_string())
+                }
+                Schema::Fixed(size) => Value::String(parse_message(self.take(*size)?)),
+                Schema::Enum(symbols) => {
+                    let index = self.len()?;
+                    Value::String(symbols.get(index).ok_or(DecodeError::Payload)?.clone())
+                }
+                // Shown as the branch's value, without Avro's JSON wrapper.
+                Schema::Union(branches) => {
+                    let index = self.len()?;
+                    self.value(branches.get(index).ok_or(DecodeError::Payload)?)?
+                }
+                Schema::Array(items) => {
+                    let mut values = vec![];
+                    self.blocks(|reader| {
+                        values.push(reader.value(items)?);
+                        Ok(())
+                    })?;
+                    Value::Array(values)
+                }
+                Schema::Map(values) => {
+                    let mut map = Map::new();
+                    self.blocks(|reader| {
+                        let key = match reader.value(&Schema::String)? {
+                            Value::String(key) => key,
+                            _ => return Err(DecodeError::Payload),
+                        };
+                        map.insert(key, reader.value(values)?);
+                        Ok(())
+                    })?;
+                    Value::Object(map)
+                }
+                Schema::Record(fields) => {
+                    let mut map = Map::new();
+                    for (name, schema) in fields {
+                        map.insert(name.clone(), self.value(schema)?);
+                    }
+                    Value::Object(map)
+                }
+            })
+        }
+    }
+
+    /// NaN and infinities have no JSON form and show as null.
+    fn number(value: f64) -> Value {
+        Number::from_f64(value).map_or(Value::Null, Value::Number)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+        use std::net::TcpListener;
+        use std::thread;
+
+        const ORDER: &str = r#"{
+            "type": "record",
+            "name": "Order",
+            "namespace": "shop",
+            "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "customer", "type": "string"},
+                {"name": "tags", "type": {"type": "array", "items": "string"}},
+                {"name": "note", "type": ["null", "string"]},
+                {"name": "status", "type": {"type": "enum", "name": "Status",
+                    "symbols": ["OPEN", "SHIPPED"]}},
+                {"name": "previous", "type": ["null", "Status"]}
+            ]
+        }"#;
+
+        /// An order with id 42, for Zoë, tagged a and b, noted "hi", shipped
+        /// and previously open.
+        const PAYLOAD: &[u8] = b"\x54\x08Zo\xc3\xab\x04\x02a\x02b\x00\x02\x04hi\x02\x02\x00";
+
+        /// The payload in the wire format, under schema id 7.
+        fn wire(payload: &[u8]) -> Vec<u8> {
+            [&[MAGIC_BYTE, 0, 0, 0, 7], payload].concat()
+        }
+
+        /// An HTTP/1.0 response with `status` and `body`.
+        fn response(status: &str, body: &str) -> Vec<u8> {
+            format!(
+                "HTTP/1.0 {}\r\nContent-Type: application/json\r\n\r\n{}",
+                status, body
+            )
+            .into_bytes()
+        }
+
+        fn order_response() -> Vec<u8> {
+            response("200 OK", &json!({ "schema": ORDER }).to_string())
+        }
+
+        /// A registry that already has `schema` under id 7.
+        fn registry(schema: Schema) -> Registry {
+            let registry = Registry::new("http://localhost:1");
+            registry
+                .schemas
+                .lock()
+                .unwrap()
+                .insert(7, Some(Arc::new(schema)));
+            registry
+        }
+
+        fn decoded(registry: &Registry, bytes: &[u8]) -> Option<Value> {
+            registry
+                .decode(bytes)
+                .map(|text| serde_json::from_str(&text).unwrap())
+        }
+
+        #[test]
+        fn records_decode_to_json() {
+            let registry = registry(schema_from_response(&order_response()).unwrap());
+            assert_eq!(
+                decoded(&registry, &wire(PAYLOAD)),
+                Some(json!({
+                    "id": 42,
+                    "customer": "Zoë",
+                    "tags": ["a", "b"],
+                    "note": "hi",
+                    "status": "SHIPPED",
+                    "previous": "OPEN",
+                }))
+            );
+        }
+
+        #[test]
+        fn primitives() {
+            let parse = |schema: Value| Parser::default().parse(&schema, None).unwrap();
+            let read =
+                |schema: Value, payload: &[u8]| decoded(&registry(parse(schema)), &wire(payload));
+            assert_eq!(read(json!("int"), b"\x01"), Some(json!(-1)));
+            assert_eq!(read(json!("long"), b"\x80\x01"), Some(json!(64)));
+            assert_eq!(read(json!("boolean"), b"\x01"), Some(json!(true)));
+            assert_eq!(
+                read(json!("double"), &1.5f64.to_le_bytes()),
+                Some(json!(1.5))
+            );
+            assert_eq!(
+                read(json!("float"), &f32::NAN.to_le_bytes()),
+                Some(json!(null))
+            );
+            assert_eq!(read(json!(["null", "long"]), b"\x00"), Some(json!(null)));
+            assert_eq!(
+                read(
+                    json!({"type": "map", "values": "int"}),
+                    b"\x02\x02k\x04\x00"
+                ),
+                Some(json!({"k": 2}))
+            );
+            assert_eq!(
+                read(
+                    json!({"type": "long", "logicalType": "timestamp-millis"}),
+                    b"\x02"
+                ),
+                Some(json!(1))
+            );
+        }
+
+        #[test]
+        fn payloads_that_dont_fit_are_not_decoded() {
+            let registry = registry(schema_from_response(&order_response()).unwrap());
+            // Not the wire format.
+            assert_eq!(registry.decode(br#"{"id": 42}"#), None);
+            // Cut short, or with bytes left over.
+            assert_eq!(registry.decode(&wire(&PAYLOAD[..6])), None);
+            assert_eq!(registry.decode(&wire(&[PAYLOAD, b"\x00"].concat())), None);
+            // A string that isn't UTF-8.
+            assert_eq!(registry.decode(&wire(b"\x54\x02\xff")), None);
+        }
+
+        #[test]
+        fn registry_responses() {
+            assert!(matches!(
+                schema_from_response(&order_response()),
+                Ok(Schema::Record(fields)) if fields.len() == 6
+            ));
+            assert!(matches!(
+                schema_from_response(&response("404 Not Found", r#"{"error_code": 40403}"#)),
+                Err(DecodeError::Status(status)) if status == "404 Not Found"
+            ));
+            for malformed in [
+                b"HTTP/1.0 200 OK".to_vec(),
+                response("200 OK", "not json"),
+                response("200 OK", r#"{"schema": "not json"}"#),
+                response("200 OK", r#"{"id": 7}"#),
+            ] {
+                assert!(matches!(
+                    schema_from_response(&malformed),
+                    Err(DecodeError::Malformed)
+                ));
+            }
+            assert!(matches!(
+                schema_from_response(&response("200 OK", r#"{"schema": "\"Unknown\""}"#)),
+                Err(DecodeError::Schema(_))
+            ));
+        }
+
+        #[test]
+        fn schemas_are_fetched_by_id_once() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/registry/", listener.local_addr().unwrap());
+            let server = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap();
+                stream.write_all(&order_response()).unwrap();
+                String::from_utf8_lossy(&request[..read]).into_owned()
+            });
+            let registry = Registry::new(&url);
+            assert!(decoded(&registry, &wire(PAYLOAD)).is_some());
+            let request = server.join().unwrap();
+            assert!(request.starts_with("GET /registry/schemas/ids/7 HTTP/1.0\r\n"));
+            // The server is gone, so this is the schema fetched before.
+            assert!(decoded(&registry, &wire(PAYLOAD)).is_some());
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+mod proto {
+    use crate::base64;
+    use serde_json::{Map, Number, Value};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::str;
+    use thiserror::Error;
+
+    /// Deeper nesting than this is taken as a malformed value rather than
+    /// followed further.
+    const MAX_DEPTH: usize = 64;
+
+    #[derive(Debug, Error)]
+    pub enum DescriptorError {
+        #[error("could not read {0}: {1}")]
+        Read(PathBuf, io::Error),
+        #[error("{0} is not a FileDescriptorSet")]
+        Malformed(PathBuf),
+        #[error("{0} has no message type {1}")]
+        UnknownType(PathBuf, String),
+    }
+
+    /// A field as it was encoded, before its declared type is applied.
+    #[derive(Clone, Copy)]
+    enum Wire<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(&'a [u8]),
+        Fixed32(u32),
+    }
+
+    struct Field {
+        name: String,
+        repeated: bool,
+        /// The `FieldDescriptorProto.Type` number, such as 9 for string.
+        kind: u64,
+        /// The fully qualified message or enum type, such as `.shop.Order`.
+        type_name: String,
+    }
+
+    struct MessageType {
+        fields: HashMap<u32, Field>,
+        /// Generated for `map<K, V>` fields, whose entries show as an object.
+        map_entry: bool,
+    }
+
+    /// The message and enum types of a descriptor set, and the one message
+    /// values are decoded as.
+    pub struct Schema {
+        messages: HashMap<String, MessageType>,
+        enums: HashMap<String, HashMap<i32, String>>,
+        root: String,
+    }
+
+    impl Schema {
+        pub fn load(path: &Path, message_type: &str) -> Result<Schema, DescriptorError> {
+            let bytes = fs::read(path).map_err(|e| DescriptorError::Read(path.to_path_buf(), e))?;
+            let mut schema = Schema {
+                messages: HashMap::new(),
+                enums: HashMap::new(),
+                root: format!(".{}", message_type.trim_start_matches('.')),
+            };
+            schema
+                .add_set(&bytes)
+                .ok_or_else(|| DescriptorError::Malformed(path.to_path_buf()))?;
+            if !schema.messages.contains_key(&schema.root) {
+                return Err(DescriptorError::UnknownType(
+                    path.to_path_buf(),
+                    message_type.to_string(),
+                ));
+            }
+            Ok(schema)
+        }
+
+        /// The value as JSON, or `None` if it isn't an encoded message of
+        /// the schema's type.
+        pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+            match self.message(&self.root, bytes, 0)? {
+                // Plenty of text and empty values parse as fields that just
+                // aren't the schema's, which says little about their type.
+                Value::Object(object) if object.is_empty() => None,
+                value => Some(value.to_string()),
+            }
+        }
+
+        fn add_set(&mut self, bytes: &[u8]) -> Option<()> {
+            for (number, wire) in split_fields(bytes)? {
+                if number == 1 {
+                    self.add_file(as_bytes(wire)?)?;
+                }
+            }
+            Some(())
+        }
+
+        fn add_file(&mut self, bytes: &[u8]) -> Option<()> {
+            let fields = split_fields(bytes)?;
+            let scope = match find_string(&fields, 2)? {
+                Some(package) => format!(".{}", package),
+                None => String::new(),
+            };
+            for &(number, wire) in &fields {
+                match number {
+                    4 => self.add_message(&scope, as_bytes(wire)?)?,
+                    5 => self.add_enum(&scope, as_bytes(wire)?)?,
+                    _ => {}
+                }
+            }
+            Some(())
+        }
+
+        fn add_message(&mut self, scope: &str, bytes: &[u8]) -> Option<()> {
+            let fields = split_fields(bytes)?;
+            let name = format!("{}.{}", scope, find_string(&fields, 1)??);
+            let mut message = MessageType {
+                fields: HashMap::new(),
+                map_entry: false,
+            };
+            for &(number, wire) in &fields {
+                match number {
+                    2 => {
+                        let (number, field) = parse_field(as_bytes(wire)?)?;
+                        message.fields.insert(number, field);
+                    }
+                    3 => self.add_message(&name, as_bytes(wire)?)?,
+                    4 => self.add_enum(&name, as_bytes(wire)?)?,
+                    7 => {
+                        message.map_entry = split_fields(as_bytes(wire)?)?
+                            .iter()
+                            .any(|&(number, wire)| number == 7 && matches!(wire, Wire::Varint(1)))
+                    }
+                    _ => {}
+                }
+            }
+            self.messages.insert(name, message);
+            Some(())
+        }
+
+        fn add_enum(&mut self, scope: &str, bytes: &[u8]) -> Option<()> {
+            let fields = split_fields(bytes)?;
+            let name = format!("{}.{}", scope, find_string(&fields, 1)??);
+            let mut values = HashMap::new();
+            for &(number, wire) in &fields {
+                if number == 2 {
+                    let value = split_fields(as_bytes(wire)?)?;
+                    let number = value.iter().find_map(|&(number, wire)| match wire {
+                        Wire::Varint(n) if number == 2 => Some(n as i32),
+                        _ => None,
+                    });
+                    values.insert(number.unwrap_or(0), find_string(&value, 1)??);
+                }
+            }
+            self.enums.insert(name, values);
+            Some(())
+        }
+
+        fn message(&self, type_name: &str, bytes: &[u8], depth: usize) -> Option<Value> {
+            if depth > MAX_DEPTH {
+                return None;
+            }
+            let message = self.messages.get(type_name)?;
+            let mut object = Map::new();
+            for (number, wire) in split_fields(bytes)? {
+                // Fields the schema doesn't know are skipped, as protobuf
+                // readers do for fields added after they were compiled.
+                let Some(field) = message.fields.get(&number) else {
+                    continue;
+                };
+                let values = match wire {
+                    Wire::Bytes(packed) if is_packable(field.kind) => self.unpack(field, packed)?,
+                    wire => vec![self.value(field, wire, depth)?],
+                };
+                let entries = self
+                    .messages
+                    .get(&field.type_name)
+                    .is_some_and(|m| m.map_entry);
+                if entries {
+                    let slot = object.entry(field.name.clone());
+                    let Value::Object(map) = slot.or_insert_with(|| Value::Object(Map::new()))
+                    else {
+                        return None;
+                    };
+                    for entry in values {
+                        let key = match entry.get("key") {
+                            Some(Value::String(key)) => key.clone(),
+                            Some(key) => key.to_string(),
+                            None => String::new(),
+                        };
+                        map.insert(key, entry.get("value").cloned().unwrap_or(Value::Null));
+                    }
+                } else if field.repeated {
+                    let slot = object.entry(field.name.clone());
+                    if let Value::Array(items) = slot.or_insert_with(|| Value::Array(vec![])) {
+                        items.extend(values);
+                    }
+                } else {
+                    // The last occurrence of a singular field wins.
+                    object.insert(field.name.clone(), values.into_iter().last()?);
+                }
+            }
+            Some(Value::Object(object))
+        }
+
+        /// Repeated numbers are usually packed into one length-delimited
+        /// field rather than tagged one by one.
+        fn unpack(&self, field: &Field, bytes: &[u8]) -> Option<Vec<Value>> {
+            let mut pos = 0;
+            let mut values = vec![];
+            while pos < bytes.len() {
+                let wire = match field.kind {
+                    1 | 6 | 16 => Wire::Fixed64(u64::from_le_bytes(
+                        take(bytes, &mut pos, 8)?.try_into().ok()?,
+                    )),
+                    2 | 7 | 15 => Wire::Fixed32(u32::from_le_bytes(
+                        take(bytes, &mut pos, 4)?.try_into().ok()?,
+                    )),
+                    _ => Wire::Varint(varint(bytes, &mut pos)?),
+                };
+                values.push(self.value(field, wire, 0)?);
+            }
+            Some(values)
+        }
+
+        /// A single value in the proto3 JSON mapping, where 64-bit integers
+        /// are strings so JavaScript doesn't round them.
+        fn value(&self, field: &Field, wire: Wire, depth: usize) -> Option<Value> {
+            Some(match (field.kind, wire) {
+                (1, Wire::Fixed64(n)) => number(f64::from_bits(n)),
+                (2, Wire::Fixed32(n)) => number(f32::from_bits(n).into()),
+                (3, Wire::Varint(n)) => Value::String((n as i64).to_string()),
+                (4, Wire::Varint(n)) | (6, Wire::Fixed64(n)) => Value::String(n.to_string()),
+                (5, Wire::Varint(n)) => Value::from(n as i32),
+                (7, Wire::Fixed32(n)) => Value::from(n),
+                (8, Wire::Varint(n)) => Value::Bool(n != 0),
+                (9, Wire::Bytes(bytes)) => Value::String(str::from_utf8(bytes).ok()?.to_string()),
+                (11, Wire::Bytes(bytes)) => self.message(&field.type_name, bytes, depth + 1)?,
+                (12, Wire::Bytes(bytes)) => Value::String(base64::encode(bytes)),
+                (13, Wire::Varint(n)) => Value::from(n as u32),
+                (14, Wire::Varint(n)) => self
+                    .enums
+                    .get(&field.type_name)
+                    .and_then(|values| values.get(&(n as i32)))
+                    .map_or(Value::from(n as i32), |name| Value::String(name.clone())),
+                (15, Wire::Fixed32(n)) => Value::from(n as i32),
+                (16, Wire::Fixed64(n)) => Value::String((n as i64).to_string()),
+                (17, Wire::Varint(n)) => Value::from(zigzag(n) as i32),
+                (18, Wire::Varint(n)) => Value::String(zigzag(n).to_string()),
+                _ => return None,
+            })
+        }
+    }
+
+    /// Reads a `FieldDescriptorProto` into its number and field.
+    fn parse_field(bytes: &[u8]) -> Option<(u32, Field)> {
+        let fields = split_fields(bytes)?;
+        let varint = |wanted| {
+            fields.iter().find_map(|&(number, wire)| match wire {
+                Wire::Varint(n) if number == wanted => Some(n),
+                _ => None,
+            })
+        };
+        let name = find_string(&fields, 1)??;
+        let field = Field {
+            // protoc fills in json_name, the lowerCamelCase form of the name.
+            name: find_string(&fields, 10)?.unwrap_or(name),
+            repeated: varint(4) == Some(3),
+            kind: varint(5)?,
+            type_name: find_string(&fields, 6)?.unwrap_or_default(),
+        };
+        Some((u32::try_from(varint(3)?).ok()?, field))
+    }
+
+    /// Splits an encoded message into its field numbers and values.
+    fn split_fields(bytes: &[u8]) -> Option<Vec<(u32, Wire<'_>)>> {
+        let mut pos = 0;
+        let mut fields = vec![];
+        while pos < bytes.len() {
+            let key = varint(bytes, &mut pos)?;
+            let number = u32::try_from(key >> 3).ok().filter(|&n| n > 0)?;
+            let wire = match key & 7 {
+                0 => Wire::Varint(varint(bytes, &mut pos)?),
+                1 => Wire::Fixed64(u64::from_le_bytes(
+                    take(bytes, &mut pos, 8)?.try_into().ok()?,
+                )),
+                2 => {
+                    let len = usize::try_from(varint(bytes, &mut pos)?).ok()?;
+                    Wire::Bytes(take(bytes, &mut pos, len)?)
+                }
+                5 => Wire::Fixed32(u32::from_le_bytes(
+                    take(bytes, &mut pos, 4)?.try_into().ok()?,
+                )),
+                // Groups are long deprecated and not supported.
+                _ => return None,
+            };
+            fields.push((number, wire));
+        }
+        Some(fields)
+    }
+
+    /// The last string with field `number`, which is `Some(None)` when it's
+    /// missing and `None` when it isn't valid UTF-8.
+    fn find_string(fields: &[(u32, Wire)], number: u32) -> Option<Option<String>> {
+        match fields.iter().rev().find(|&&(n, _)| n == number) {
+            Some(&(_, wire)) => Some(Some(str::from_utf8(as_bytes(wire)?).ok()?.to_string())),
+            None => Some(None),
+        }
+    }
+
+    fn as_bytes(wire: Wire<'_>) -> Option<&[u8]> {
+        match wire {
+            Wire::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Strings, groups, messages and bytes are never packed.
+    fn is_packable(kind: u64) -> bool {
+        !(9..=12).contains(&kind)
+    }
+
+    fn varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *bytes.get(*pos)?;
+            *pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn take<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> Option<&'a [u8]> {
+        let end = pos.checked_add(len)?;
+        let taken = bytes.get(*pos..end)?;
+        *pos = end;
+        Some(taken)
+    }
+
+    fn zigzag(n: u64) -> i64 {
+        (n >> 1) as i64 ^ -((n & 1) as i64)
+    }
+
+    /// NaN and infinities have no JSON form and show as null.
+    fn number(value: f64) -> Value {
+        Number::from_f64(value).map_or(Value::Null, Value::Number)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::decode::Decoder;
+        use serde_json::json;
+        use std::env;
+        use std::process;
+
+        const STRING: u64 = 9;
+        const INT64: u64 = 3;
+        const INT32: u64 = 5;
+        const MESSAGE: u64 = 11;
+        const OPTIONAL: u64 = 1;
+        const REPEATED: u64 = 3;
+
+        fn encode_varint(mut n: u64) -> Vec<u8> {
+            let mut bytes = vec![];
+            while n >= 0x80 {
+                bytes.push(n as u8 | 0x80);
+                n >>= 7;
+            }
+            bytes.push(n as u8);
+            bytes
+        }
+
+        fn varint_field(number: u32, n: u64) -> Vec<u8> {
+            [encode_varint(u64::from(number) << 3), encode_varint(n)].concat()
+        }
+
+        fn bytes_field(number: u32, bytes: &[u8]) -> Vec<u8> {
+            [
+                encode_varint(u64::from(number) << 3 | 2),
+                encode_varint(bytes.len() as u64),
+                bytes.to_vec(),
+            ]
+            .concat()
+        }
+
+        /// A `FieldDescriptorProto`.
+        fn field(name: &str, number: u64, label: u64, kind: u64, type_name: &str) -> Vec<u8> {
+            [
+                bytes_field(1, name.as_bytes()),
+                varint_field(3, number),
+                varint_field(4, label),
+                varint_field(5, kind),
+                bytes_field(6, type_name.as_bytes()),
+            ]
+            .concat()
+        }
+
+        /// `shop.Order` and its nested `Customer`, as protoc would describe
+        /// them in a descriptor set written to a file named after `test`.
+        fn schema(test: &str) -> PathBuf {
+            let customer = [
+                bytes_field(1, b"Customer"),
+                bytes_field(2, &field("name", 1, OPTIONAL, STRING, "")),
+            ]
+            .concat();
+            let order = [
+                bytes_field(1, b"Order"),
+                bytes_field(2, &field("id", 1, OPTIONAL, STRING, "")),
+                bytes_field(2, &field("total", 2, OPTIONAL, INT64, "")),
+                bytes_field(2, &field("tags", 3, REPEATED, STRING, "")),
+                bytes_field(
+                    2,
+                    &field("customer", 4, OPTIONAL, MESSAGE, ".shop.Order.Customer"),
+                ),
+                bytes_field(2, &field("counts", 5, REPEATED, INT32, "")),
+                bytes_field(3, &customer),
+            ]
+            .concat();
+            let file = [
+                bytes_field(1, b"shop.proto"),
+                bytes_field(2, b"shop"),
+                bytes_field(4, &order),
+            ]
+            .concat();
+            let path = env::temp_dir().join(format!("kafku-{}-{}.desc", test, process::id()));
+            fs::write(&path, bytes_field(1, &file)).unwrap();
+            path
+        }
+
+        fn decode(test: &str, bytes: &[u8]) -> Option<Value> {
+            let schema = Schema::load(&schema(test), "shop.Order").unwrap();
+            schema
+                .decode(bytes)
+                .map(|json| serde_json::from_str(&json).unwrap())
+        }
+
+        #[test]
+        fn known_message() {
+            let order = [bytes_field(1, b"o-1"), varint_field(2, 1234)].concat();
+            assert_eq!(
+                decode("known", &order),
+                Some(json!({"id": "o-1", "total": "1234"}))
+            );
+        }
+
+        #[test]
+        fn unknown_fields_are_skipped() {
+            let order = [
+                varint_field(99, 7),
+                bytes_field(1, b"o-1"),
+                bytes_field(100, b"later"),
+            ]
+            .concat();
+            assert_eq!(decode("unknown", &order), Some(json!({"id": "o-1"})));
+            // Nothing but unknown fields isn't taken for an order.
+            assert_eq!(decode("unknown", &varint_field(99, 7)), None);
+        }
+
+        #[test]
+        fn nested_messages() {
+            let customer = bytes_field(1, b"Ann");
+            let order = [bytes_field(1, b"o-1"), bytes_field(4, &customer)].concat();
+            assert_eq!(
+                decode("nested", &order),
+                Some(json!({"id": "o-1", "customer": {"name": "Ann"}}))
+            );
+        }
+
+        #[test]
+        fn repeated_fields() {
+            let packed = [encode_varint(1), encode_varint(300)].concat();
+            let order = [
+                bytes_field(3, b"gift"),
+                bytes_field(3, b"express"),
+                bytes_field(5, &packed),
+                // Unpacked values add to the packed ones.
+                varint_field(5, 2),
+            ]
+            .concat();
+            assert_eq!(
+                decode("repeated", &order),
+                Some(json!({"tags": ["gift", "express"], "counts": [1, 300, 2]}))
+            );
+        }
+
+        #[test]
+        fn junk_payloads_fall_back_to_text() {
+            assert_eq!(decode("junk", b""), None);
+            // A varint where the schema declares a string.
+            assert_eq!(decode("junk", &varint_field(1, 1)), None);
+            // A string cut short.
+            let order = bytes_field(1, b"o-1");
+            assert_eq!(decode("junk", &order[..order.len() - 1]), None);
+
+            let path = schema("junk-text");
+            let decoder = Decoder::new(None, Some((&path, "shop.Order")));
+            assert_eq!(decoder.decode(b"hello world"), "hello world");
+            assert_eq!(decoder.decode(b""), "");
+            assert_eq!(decoder.decode(&bytes_field(1, b"o-1")), r#"{"id":"o-1"}"#);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+/// A small multi-field text form used by the TUI's popup dialogs.
+pub struct Form {
+    pub title: String,
+    pub fields: Vec<Field>,
+    pub active: usize,
+}
+
+pub struct Field {
+    pub label: &'static str,
+    pub value: String,
+}
+
+impl Form {
+    pub fn new(title: &str, labels: &[&'static str]) -> Form {
+        Form {
+            title: title.to_string(),
+            fields: labels
+                .iter()
+                .map(|label| Field {
+                    label,
+                    value: String::new(),
+                })
+                .collect(),
+            active: 0,
+        }
+    }
+
+    pub fn with_value(mut self, index: usize, value: &str) -> Form {
+        self.fields[index].value = value.to_string();
+        self
+    }
+
+    pub fn value(&self, index: usize) -> &str {
+        self.fields[index].value.trim()
+    }
+
+    pub fn input(&mut self, c: char) {
+        self.fields[self.active].value.push(c);
+    }
+
+    pub fn backspace(&mut self) {
+        self.fields[self.active].value.pop();
+    }
+
+    pub fn cycle(&mut self) {
+        self.active = (self.active + 1) % self.fields.len();
+    }
+
+    /// Moves to the next field, returning false when already on the last one.
+    pub fn next_field(&mut self) -> bool {
+        if self.active + 1 < self.fields.len() {
+            self.active += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,131 +1,1342 @@
-use kafka::client::metadata::Topic;
-use kafka::client::{KafkaClient, PartitionOffset};
-use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use crate::admin::{AdminClient, AdminError, GroupOffset, PartitionReplicas, TopicConfig};
+use crate::base64;
+use crate::config::{Acks, Compression, ConsumerConfig, ProducerConfig, TlsConfig};
+use crate::decode::Decoder;
+use crate::pattern::{PatternError, Regex};
+use kafka::client::{CommitOffset, FetchPartition, KafkaClient, PartitionOffset, SecurityConfig};
+use kafka::consumer::{Builder, Consumer, FetchOffset, GroupOffsetStorage, Message};
+use kafka::error::KafkaCode;
 use kafka::producer::{Producer, Record, RequiredAcks};
-use std::fmt::Write;
+use log::{debug, info, warn};
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+use serde::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
 use std::str;
-use std::time::Duration;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
+/// How long a poll waits for new messages, short so that a followed topic
+/// shows new messages promptly and a pull session closes quickly.
+const FETCH_MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// How long to wait before the first retry of a failed refresh, doubled
+/// before each one after that.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Shown as the leader of partitions that have none.
+const NO_LEADER: &str = "No Leader Available";
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Partition {
     pub id: i32,
     pub leader: String,
     pub available: bool,
-    pub offset: i64
+    /// The oldest offset still retained.
+    pub earliest_offset: i64,
+    pub offset: i64,
+    /// The consumer group's committed offset, if it has committed one.
+    pub committed_offset: Option<i64>,
+    /// Ids of the brokers holding a copy, empty if they couldn't be loaded.
+    pub replicas: Vec<i32>,
+    /// Ids of the replicas caught up with the leader.
+    pub isr: Vec<i32>,
+    /// Bytes on disk of the partition's largest replica, if the brokers
+    /// report their log directories.
+    pub size: Option<i64>,
 }
-#[derive(Debug, Clone)]
+
+impl Partition {
+    /// False while the partition's leader is down, in which case it can be
+    /// neither read nor written.
+    pub fn has_leader(&self) -> bool {
+        self.leader != NO_LEADER
+    }
+
+    /// Whether some replicas fell behind the leader, leaving fewer copies
+    /// than configured to take over should the leader fail.
+    pub fn is_under_replicated(&self) -> bool {
+        self.isr.len() < self.replicas.len()
+    }
+
+    /// Messages the consumer group has yet to consume from this partition.
+    pub fn lag(&self) -> Option<i64> {
+        self.committed_offset
+            .map(|committed| (self.offset - committed).max(0))
+    }
+
+    /// Messages the partition still holds, as opposed to the high-water
+    /// mark, which also counts those removed by retention.
+    pub fn retained(&self) -> i64 {
+        (self.offset - self.earliest_offset).max(0)
+    }
+}
+
+/// The brokers and topic count shown in the Info tab, from one snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct ClusterSummary {
+    pub brokers: Vec<String>,
+    pub topic_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct TopicData {
     pub name: String,
     pub partitions: Vec<Partition>,
 }
 
+impl TopicData {
+    /// The sum of the partitions' latest offsets. This overcounts topics whose
+    /// old messages were removed by retention, but is a cheap upper bound.
+    pub fn message_count(&self) -> i64 {
+        self.partitions.iter().map(|p| p.offset).sum()
+    }
+
+    /// Bytes on disk of one copy of the topic, or None if no partition's
+    /// size is known.
+    pub fn size(&self) -> Option<i64> {
+        self.partitions
+            .iter()
+            .filter_map(|p| p.size)
+            .reduce(|a, b| a + b)
+    }
+}
+
+/// Where `reset_group_offsets` moves a group to.
+#[derive(Debug, Clone, Copy)]
+pub enum ResetTarget {
+    Earliest,
+    Latest,
+    Offset(i64),
+}
+
+/// A partition's committed offset before and after a reset.
+#[derive(Debug, Clone)]
+pub struct OffsetReset {
+    pub partition: i32,
+    pub before: Option<i64>,
+    pub after: i64,
+}
+
+/// Where the broker wrote a produced message.
+#[derive(Debug, Clone, Copy)]
+pub struct Delivery {
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// The messages to keep by key: those whose key starts with the text, or
+/// matches the regex after a leading `/`, like the topic filter's.
+#[derive(Debug, Default)]
+pub struct KeyFilter {
+    text: String,
+    regex: Option<Regex>,
+}
+
+impl KeyFilter {
+    pub fn new(text: &str) -> Result<KeyFilter, PatternError> {
+        Ok(KeyFilter {
+            text: text.to_string(),
+            regex: text.strip_prefix('/').map(Regex::new).transpose()?,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// How the filter is shown in the Messages title: the regex as typed, or
+    /// the prefix followed by `*`.
+    pub fn label(&self) -> String {
+        match self.regex {
+            Some(_) => self.text.clone(),
+            None => format!("{}*", self.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsumedMessage {
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub value: String,
+    /// The value as it was on the wire, before decoding. Written as base64,
+    /// so replaying an export sends the same bytes.
+    #[serde(serialize_with = "to_base64")]
+    pub raw: Vec<u8>,
+}
+
+fn to_base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(bytes))
+}
+
+impl ConsumedMessage {
+    pub fn from_kafka(partition: i32, message: &Message, decoder: &Decoder) -> ConsumedMessage {
+        ConsumedMessage {
+            partition,
+            offset: message.offset,
+            key: parse_key(message.key),
+            value: decoder.decode(message.value),
+            raw: message.value.to_vec(),
+        }
+    }
+
+    /// The raw value as `xxd` would print it: an offset, sixteen bytes in
+    /// hex and the same bytes as ASCII, with `.` for anything unprintable.
+    pub fn hex_dump(&self) -> Vec<String> {
+        self.raw
+            .chunks(16)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let mut line = format!("{:08x}: ", row * 16);
+                for i in 0..16 {
+                    match bytes.get(i) {
+                        Some(byte) => {
+                            let _ = write!(line, "{:02x}", byte);
+                        }
+                        None => line.push_str("  "),
+                    }
+                    if i % 2 == 1 {
+                        line.push(' ');
+                    }
+                }
+                line.push(' ');
+                line.extend(bytes.iter().map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                }));
+                line
+            })
+            .collect()
+    }
+
+    /// The partition, offset and key shown before the value.
+    pub fn heading(&self) -> String {
+        let key = self.key.as_deref().unwrap_or("<null>");
+        format!("p{:<3} @{:<10} {} =>", self.partition, self.offset, key)
+    }
+
+    /// Whether `filter` keeps the message. An empty filter keeps every
+    /// message, including those without a key.
+    pub fn key_matches(&self, filter: &KeyFilter) -> bool {
+        match (&filter.regex, self.key.as_deref()) {
+            _ if filter.text.is_empty() => true,
+            (_, None) => false,
+            (Some(regex), Some(key)) => regex.is_match(key),
+            (None, Some(key)) => key.starts_with(&filter.text),
+        }
+    }
+
+    /// The value re-indented over several lines, if it parses as JSON.
+    pub fn pretty_value(&self) -> Option<String> {
+        let json: serde_json::Value = serde_json::from_str(&self.value).ok()?;
+        serde_json::to_string_pretty(&json).ok()
+    }
+}
+
+impl fmt::Display for ConsumedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.heading(), self.value)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KafkaClientError {
+    #[error("TLS file not found at {0}")]
+    MissingTlsFile(PathBuf),
+    #[error("could not load TLS file {0}: {1}")]
+    InvalidTlsFile(PathBuf, ErrorStack),
+    #[error("could not set up TLS: {0}")]
+    Tls(ErrorStack),
+    #[error(transparent)]
+    Kafka(#[from] kafka::Error),
+    #[error(transparent)]
+    Admin(#[from] AdminError),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(
+        "offset {offset} is out of range for partition {partition}, \
+         which holds offsets {earliest} up to {latest}"
+    )]
+    OffsetOutOfRange {
+        partition: i32,
+        offset: i64,
+        earliest: i64,
+        latest: i64,
+    },
+    #[error("could not read partition {0}: {1}")]
+    Fetch(i32, String),
+}
+
+impl KafkaClientError {
+    /// Whether trying again may succeed, as when a connection dropped or a
+    /// partition is between leaders.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            KafkaClientError::Kafka(kafka::Error::Io(_))
+            | KafkaClientError::Kafka(kafka::Error::NoHostReachable)
+            | KafkaClientError::Kafka(kafka::Error::UnexpectedEOF) => true,
+            KafkaClientError::Kafka(kafka::Error::Kafka(code)) => matches!(
+                code,
+                KafkaCode::LeaderNotAvailable
+                    | KafkaCode::NotLeaderForPartition
+                    | KafkaCode::RequestTimedOut
+                    | KafkaCode::BrokerNotAvailable
+                    | KafkaCode::NetworkException
+                    | KafkaCode::GroupLoadInProgress
+                    | KafkaCode::GroupCoordinatorNotAvailable
+                    | KafkaCode::NotCoordinatorForGroup
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Tracks the attempts at an operation that may fail transiently, backing
+/// off exponentially between them.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    attempt: u32,
+    attempts: u32,
+    /// The wait before the second attempt.
+    backoff: Duration,
+}
+
+impl Retry {
+    pub fn new(attempts: u32) -> Retry {
+        Retry {
+            attempt: 1,
+            attempts: attempts.max(1),
+            backoff: RETRY_BACKOFF,
+        }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Moves on to the next attempt if `error` is transient and attempts
+    /// are left. Call `backoff` before making it.
+    pub fn retry(&mut self, error: &KafkaClientError) -> bool {
+        if self.attempt >= self.attempts || !error.is_transient() {
+            return false;
+        }
+        self.attempt += 1;
+        warn!(
+            "retrying (attempt {}/{}) after: {}",
+            self.attempt, self.attempts, error
+        );
+        true
+    }
+
+    /// Waits out the backoff before the current attempt; the first one
+    /// doesn't wait.
+    pub fn backoff(&self) {
+        if self.attempt > 1 {
+            thread::sleep(self.backoff * 2u32.pow(self.attempt - 2));
+        }
+    }
+
+    /// Runs `op` until it succeeds, fails for good or runs out of attempts,
+    /// calling `on_retry` with the attempt about to be made.
+    pub fn run<T>(
+        &mut self,
+        mut op: impl FnMut() -> Result<T, KafkaClientError>,
+        mut on_retry: impl FnMut(&Retry),
+    ) -> Result<T, KafkaClientError> {
+        loop {
+            match op() {
+                Err(e) if self.retry(&e) => {
+                    on_retry(self);
+                    self.backoff();
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
 pub struct SimpleKafkaClient {
-    pub hosts: Vec<String>,
+    hosts: Vec<String>,
+    tls: Option<SslConnector>,
+    admin: AdminClient,
+    client: RefCell<KafkaClient>,
+    topics: RefCell<Vec<TopicData>>,
+    consumer: ConsumerConfig,
+    producer: ProducerConfig,
+    decoder: Arc<Decoder>,
 }
 
 impl SimpleKafkaClient {
-    pub fn create(&self) -> KafkaClient {
-        KafkaClient::new(self.hosts.clone())
+    /// Prepares a client for `hosts`, over TLS when `tls` is given. No
+    /// connection is made until the first `refresh`; the connection is then
+    /// kept open and reused by every later one.
+    ///
+    /// The kafka crate has no connect timeout of its own and instead retries
+    /// unreachable brokers, up to `request_retries` times. `connect_timeout`
+    /// bounds the admin requests kafku makes itself.
+    pub fn new(
+        hosts: Vec<String>,
+        tls: Option<&TlsConfig>,
+        connect_timeout: Duration,
+        request_retries: u32,
+        consumer: ConsumerConfig,
+        producer: ProducerConfig,
+        decoder: Decoder,
+    ) -> Result<SimpleKafkaClient, KafkaClientError> {
+        let tls = tls.map(tls_connector).transpose()?;
+        let client = match &tls {
+            Some(connector) => {
+                KafkaClient::new_secure(hosts.clone(), SecurityConfig::new(connector.clone()))
+            }
+            None => KafkaClient::new(hosts.clone()),
+        };
+        let mut client = client;
+        client.set_group_offset_storage(GroupOffsetStorage::Kafka);
+        // Also used when reading from an offset, which fetches directly.
+        client.set_fetch_min_bytes(consumer.fetch_min_bytes);
+        client.set_fetch_max_bytes_per_partition(consumer.fetch_max_bytes);
+        client.set_retry_max_attempts(request_retries);
+        let client = SimpleKafkaClient {
+            admin: AdminClient::new(hosts.clone(), tls.clone(), connect_timeout),
+            hosts,
+            tls,
+            client: RefCell::new(client),
+            topics: RefCell::new(vec![]),
+            consumer,
+            producer,
+            decoder: Arc::new(decoder),
+        };
+        Ok(client)
+    }
+
+    /// Turns message values into text; shared with pull sessions' threads.
+    pub fn decoder(&self) -> Arc<Decoder> {
+        Arc::clone(&self.decoder)
+    }
+
+    fn security(&self) -> Option<SecurityConfig> {
+        self.tls.clone().map(SecurityConfig::new)
+    }
+
+    /// Reloads topic metadata, offsets and `group`'s committed offsets into
+    /// the cached snapshot.
+    pub fn refresh(&self, group: &str) -> Result<(), KafkaClientError> {
+        debug!("loading metadata from {}", self.hosts.join(", "));
+        let topics = self.load_topic_details(group).map_err(|e| {
+            warn!("could not load metadata: {}", e);
+            e
+        })?;
+        info!("loaded metadata for {} topics", topics.len());
+        *self.topics.borrow_mut() = topics;
+        Ok(())
+    }
+
+    /// Like `refresh`, but tries up to `attempts` times while failures look
+    /// transient, calling `on_retry` with the attempt about to be made.
+    pub fn refresh_with_retry(
+        &self,
+        group: &str,
+        attempts: u32,
+        on_retry: impl FnMut(&Retry),
+    ) -> Result<(), KafkaClientError> {
+        Retry::new(attempts).run(|| self.refresh(group), on_retry)
     }
 
     pub fn list_topics(&self) -> Vec<String> {
-        let mut client = self.create();
-        client.load_metadata_all().unwrap();
-        return client
-            .topics()
+        self.topics
+            .borrow()
             .iter()
-            .map(|topic: Topic| topic.name().to_string())
-            .collect();
+            .map(|topic| topic.name.clone())
+            .collect()
     }
 
     pub fn list_brokers(&self) -> Vec<String> {
-        let mut client = self.create();
-        return client.hosts().to_owned();
+        self.hosts.clone()
     }
 
-    fn get_offsets(&self, topic: String ) -> Vec<PartitionOffset> {
-        let mut client = self.create();
-        client.load_metadata_all().unwrap();
-        return client.fetch_topic_offsets(topic, FetchOffset::Latest).unwrap();
+    /// The brokers and number of topics as of the last `refresh`, read
+    /// together so the Info tab doesn't need a call for each.
+    pub fn cluster_summary(&self) -> ClusterSummary {
+        ClusterSummary {
+            brokers: self.list_brokers(),
+            topic_count: self.topics.borrow().len(),
+        }
+    }
+
+    /// Asks each of `hosts` for metadata on a thread of its own, so a dead
+    /// broker holds up neither the UI nor the other checks, and sends back
+    /// each host with whether it answered. An unreachable broker is the
+    /// answer rather than an error.
+    pub fn check_brokers(&self, hosts: &[String]) -> Receiver<(String, bool)> {
+        let (tx, rx) = mpsc::channel();
+        for host in hosts {
+            let admin = self.admin.clone();
+            let tx = tx.clone();
+            let host = host.clone();
+            thread::spawn(move || {
+                let healthy = match admin.ping(&host) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("broker {} is unreachable: {}", host, e);
+                        false
+                    }
+                };
+                // Nobody is listening once a newer check has started.
+                let _ = tx.send((host, healthy));
+            });
+        }
+        rx
+    }
+
+    /// Messages per second arriving on `topic`, from the sum of its latest
+    /// offsets sampled `interval` apart. Blocks for at least `interval`.
+    pub fn sample_topic_rate(
+        &self,
+        topic: &str,
+        interval: Duration,
+    ) -> Result<f64, KafkaClientError> {
+        let before = self.latest_offset_sum(topic)?;
+        let started = Instant::now();
+        thread::sleep(interval);
+        let after = self.latest_offset_sum(topic)?;
+        // Offsets only grow, unless the topic was recreated in between.
+        Ok((after - before).max(0) as f64 / started.elapsed().as_secs_f64())
+    }
+
+    fn latest_offset_sum(&self, topic: &str) -> Result<i64, KafkaClientError> {
+        let mut client = self.client.borrow_mut();
+        let offsets = Self::get_offsets(&mut client, topic, FetchOffset::Latest)?;
+        Ok(offsets.iter().map(|o| o.offset).sum())
     }
 
     pub fn list_topic_details(&self) -> Vec<TopicData> {
-        let mut client = self.create();
-        client.load_metadata_all().unwrap();
-        client
+        self.topics.borrow().clone()
+    }
+
+    fn get_offsets(
+        client: &mut KafkaClient,
+        topic: &str,
+        at: FetchOffset,
+    ) -> Result<Vec<PartitionOffset>, KafkaClientError> {
+        Ok(client.fetch_topic_offsets(topic, at)?)
+    }
+
+    /// Offsets `group` has committed for each partition of `topic`. Partitions
+    /// without a commit are reported with an offset of -1.
+    fn fetch_group_offsets(
+        client: &mut KafkaClient,
+        group: &str,
+        topic: &str,
+    ) -> Result<Vec<PartitionOffset>, KafkaClientError> {
+        Ok(client.fetch_group_topic_offsets(group, topic)?)
+    }
+
+    fn load_topic_details(&self, group: &str) -> Result<Vec<TopicData>, KafkaClientError> {
+        let mut client = self.client.borrow_mut();
+        client.load_metadata_all()?;
+        let mut topics: Vec<TopicData> = client
             .topics()
             .iter()
             .map(|topic| {
-                let offsets = self.get_offsets(topic.name().to_string());
                 let partitions = topic
                     .partitions()
                     .iter()
-                    .map(|p| {
-                        let offset = offsets.iter().find(|o| o.partition == p.id());
-                        Partition {
+                    .map(|p| Partition {
                         id: p.id(),
                         leader: p
                             .leader()
                             .map(|l| l.host())
-                            .unwrap_or("No Leader Available")
+                            .unwrap_or(NO_LEADER)
                             .to_string(),
                         available: p.is_available(),
-                        offset: offset.map(|o| o.offset).unwrap_or(0)
-                        }
+                        earliest_offset: 0,
+                        offset: 0,
+                        committed_offset: None,
+                        replicas: vec![],
+                        isr: vec![],
+                        size: None,
                     })
                     .collect();
                 TopicData {
                     name: topic.name().to_string(),
-                    partitions: partitions,
+                    partitions,
                 }
             })
-            .collect()
+            .collect();
+        // Like lag, replicas are best effort.
+        let mut replicas: HashMap<(String, i32), PartitionReplicas> = self
+            .admin
+            .partition_replicas()
+            .unwrap_or_else(|e| {
+                warn!("could not load partition replicas: {}", e);
+                vec![]
+            })
+            .into_iter()
+            .map(|r| ((r.topic.clone(), r.partition), r))
+            .collect();
+        // The largest replica of each partition, the one furthest along.
+        let mut sizes: HashMap<(String, i32), i64> = HashMap::new();
+        for replica in self.admin.log_dir_sizes().unwrap_or_else(|e| {
+            warn!("could not load log dir sizes: {}", e);
+            vec![]
+        }) {
+            let size = sizes.entry((replica.topic, replica.partition)).or_default();
+            *size = (*size).max(replica.size);
+        }
+        for topic in topics.iter_mut() {
+            let earliest = Self::get_offsets(&mut client, &topic.name, FetchOffset::Earliest)?;
+            let offsets = Self::get_offsets(&mut client, &topic.name, FetchOffset::Latest)?;
+            // Lag is best effort: a cluster without a group coordinator yet
+            // should still list its topics.
+            let committed =
+                Self::fetch_group_offsets(&mut client, group, &topic.name).unwrap_or_default();
+            for partition in topic.partitions.iter_mut() {
+                partition.earliest_offset = earliest
+                    .iter()
+                    .find(|o| o.partition == partition.id)
+                    .map(|o| o.offset)
+                    .unwrap_or(0);
+                partition.offset = offsets
+                    .iter()
+                    .find(|o| o.partition == partition.id)
+                    .map(|o| o.offset)
+                    .unwrap_or(0);
+                partition.committed_offset = committed
+                    .iter()
+                    .find(|o| o.partition == partition.id && o.offset >= 0)
+                    .map(|o| o.offset);
+                let key = (topic.name.clone(), partition.id);
+                if let Some(PartitionReplicas { replicas, isr, .. }) = replicas.remove(&key) {
+                    partition.replicas = replicas;
+                    partition.isr = isr;
+                }
+                partition.size = sizes.get(&key).copied();
+            }
+        }
+        Ok(topics)
+    }
+
+    /// Creates a topic after checking the request makes sense for the
+    /// cluster, so obvious mistakes don't round-trip to the controller.
+    pub fn create_topic(
+        &self,
+        name: &str,
+        partitions: i32,
+        replication_factor: i16,
+    ) -> Result<(), KafkaClientError> {
+        if name.is_empty() {
+            return Err(KafkaClientError::Invalid("topic name is empty".to_string()));
+        }
+        if partitions < 1 {
+            return Err(KafkaClientError::Invalid(
+                "a topic needs at least one partition".to_string(),
+            ));
+        }
+        let brokers = self.admin.metadata()?.brokers.len();
+        if replication_factor < 1 || replication_factor as usize > brokers {
+            return Err(KafkaClientError::Invalid(format!(
+                "replication factor must be between 1 and the number of brokers ({})",
+                brokers
+            )));
+        }
+        Ok(self
+            .admin
+            .create_topic(name, partitions, replication_factor)?)
+    }
+
+    pub fn list_consumer_groups(&self) -> Result<Vec<String>, KafkaClientError> {
+        Ok(self.admin.list_groups()?)
+    }
+
+    pub fn group_offsets(&self, group: &str) -> Result<Vec<GroupOffset>, KafkaClientError> {
+        Ok(self.admin.group_offsets(group)?)
     }
 
-    pub fn create_consumer(&self, topic: &str) -> Consumer {
-        // println!("Consumer group set to {}", whoami::username());
-        Consumer::from_hosts(self.hosts.clone())
-        .with_topic(topic.to_owned())
-            // .with_topic_partitions(topic.to_owned(), &[partition])
-            .with_fallback_offset(FetchOffset::Earliest)
-            // .with_group(whoami::username().to_owned())
-            .with_group("somegroup3".to_owned())
-            .with_offset_storage(GroupOffsetStorage::Kafka)
-            .create()
-            .unwrap()
+    /// The configuration of topic `name`, such as `retention.ms`.
+    pub fn describe_topic_configs(&self, name: &str) -> Result<Vec<TopicConfig>, KafkaClientError> {
+        Ok(self.admin.describe_topic_configs(name)?)
     }
 
-    pub fn start_consumer(&self, mut consumer: Consumer, f: &mut dyn FnMut(std::string::String)) {
+    /// Commits new offsets for `group` on every partition of `topic` without
+    /// consuming anything, so the group next reads from `target`. A specific
+    /// offset must lie within every partition. Brokers refuse the commit
+    /// while the group has active members.
+    pub fn reset_group_offsets(
+        &self,
+        group: &str,
+        topic: &str,
+        target: ResetTarget,
+    ) -> Result<Vec<OffsetReset>, KafkaClientError> {
+        let mut client = self.client.borrow_mut();
+        let earliest = client.fetch_topic_offsets(topic, FetchOffset::Earliest)?;
+        let latest = client.fetch_topic_offsets(topic, FetchOffset::Latest)?;
+        let before = Self::fetch_group_offsets(&mut client, group, topic)?;
+        let mut resets = vec![];
+        for bound in &earliest {
+            let partition = bound.partition;
+            let latest = latest
+                .iter()
+                .find(|o| o.partition == partition)
+                .map(|o| o.offset)
+                .unwrap_or(bound.offset);
+            let after = match target {
+                ResetTarget::Earliest => bound.offset,
+                ResetTarget::Latest => latest,
+                ResetTarget::Offset(offset) if offset < bound.offset || offset > latest => {
+                    return Err(KafkaClientError::OffsetOutOfRange {
+                        partition,
+                        offset,
+                        earliest: bound.offset,
+                        latest,
+                    })
+                }
+                ResetTarget::Offset(offset) => offset,
+            };
+            resets.push(OffsetReset {
+                partition,
+                before: before
+                    .iter()
+                    .find(|o| o.partition == partition && o.offset >= 0)
+                    .map(|o| o.offset),
+                after,
+            });
+        }
+        resets.sort_by_key(|reset| reset.partition);
+        client.commit_offsets(
+            group,
+            resets
+                .iter()
+                .map(|reset| CommitOffset::new(topic, reset.partition, reset.after)),
+        )?;
+        Ok(resets)
+    }
+
+    /// Deletes `name` and drops it from the cached topic list.
+    pub fn delete_topic(&self, name: &str) -> Result<(), KafkaClientError> {
+        self.admin.delete_topic(name)?;
+        self.topics.borrow_mut().retain(|topic| topic.name != name);
+        Ok(())
+    }
+
+    /// Creates a consumer for `topic` that commits its offsets under `group`.
+    ///
+    /// With `FetchOffset::Latest` the group is left out, so previously
+    /// committed offsets don't replay history and only new messages show up.
+    pub fn create_consumer(
+        &self,
+        topic: &str,
+        group: &str,
+        offset: FetchOffset,
+    ) -> kafka::Result<Consumer> {
+        self.consumer_builder(topic, None, group, offset).create()
+    }
+
+    /// Sets up what `create_consumer` creates, reading only `partition` of
+    /// `topic` when given. Nothing connects until `create` is called on the
+    /// builder, which may happen on another thread.
+    pub fn consumer_builder(
+        &self,
+        topic: &str,
+        partition: Option<i32>,
+        group: &str,
+        offset: FetchOffset,
+    ) -> Builder {
+        let builder = Consumer::from_hosts(self.hosts.clone());
+        let builder = match partition {
+            Some(partition) => builder.with_topic_partitions(topic.to_owned(), &[partition]),
+            None => builder.with_topic(topic.to_owned()),
+        };
+        let builder = builder
+            .with_fetch_max_wait_time(FETCH_MAX_WAIT)
+            .with_fetch_min_bytes(self.consumer.fetch_min_bytes)
+            .with_fetch_max_bytes_per_partition(self.consumer.fetch_max_bytes)
+            .with_fallback_offset(offset)
+            .with_offset_storage(GroupOffsetStorage::Kafka);
+        let builder = match offset {
+            FetchOffset::Latest => builder,
+            _ => builder.with_group(group.to_owned()),
+        };
+        match self.security() {
+            Some(security) => builder.with_security(security),
+            None => builder,
+        }
+    }
+
+    /// Reads up to `limit` messages of `partition` starting at `offset`,
+    /// without a consumer group so no offsets are committed. The kafka crate's
+    /// consumers can only start at a committed offset or at either end of a
+    /// partition, so this fetches from the partition directly.
+    pub fn read_from_offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        limit: usize,
+    ) -> Result<Vec<ConsumedMessage>, KafkaClientError> {
+        let mut client = self.client.borrow_mut();
+        let mut bound = |at: FetchOffset| -> Result<i64, KafkaClientError> {
+            client
+                .fetch_topic_offsets(topic, at)?
+                .iter()
+                .find(|o| o.partition == partition)
+                .map(|o| o.offset)
+                .ok_or_else(|| {
+                    KafkaClientError::Invalid(format!("{} has no partition {}", topic, partition))
+                })
+        };
+        let earliest = bound(FetchOffset::Earliest)?;
+        let latest = bound(FetchOffset::Latest)?;
+        if offset < earliest || offset > latest {
+            return Err(KafkaClientError::OffsetOutOfRange {
+                partition,
+                offset,
+                earliest,
+                latest,
+            });
+        }
+        let mut messages: Vec<ConsumedMessage> = vec![];
+        let mut next = offset;
+        while messages.len() < limit && next < latest {
+            let responses = client
+                .fetch_messages_for_partition(&FetchPartition::new(topic, partition, next))?;
+            let read = messages.len();
+            for p in responses
+                .iter()
+                .flat_map(|r| r.topics())
+                .flat_map(|t| t.partitions())
+            {
+                let data = p
+                    .data()
+                    .map_err(|e| KafkaClientError::Fetch(partition, e.to_string()))?;
+                // Compressed message sets can start before the requested offset.
+                for m in data.messages().iter().filter(|m| m.offset >= next) {
+                    if messages.len() < limit {
+                        messages.push(ConsumedMessage::from_kafka(partition, m, &self.decoder));
+                    }
+                }
+            }
+            match messages.last() {
+                Some(last) if messages.len() > read => next = last.offset + 1,
+                // A message larger than the fetch size never arrives.
+                _ => break,
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The offset each partition of `topic` had at `timestamp`, in
+    /// milliseconds since the epoch. The kafka crate only speaks the first
+    /// version of the offsets API, which answers with the start of the log
+    /// segment written up to then, so reading from there can include
+    /// somewhat older messages. Partitions written to only after `timestamp`
+    /// start at their earliest offset, and those not written to since at
+    /// their latest.
+    pub fn offsets_for_times(
+        &self,
+        topic: &str,
+        timestamp: i64,
+    ) -> Result<Vec<PartitionOffset>, KafkaClientError> {
+        let mut client = self.client.borrow_mut();
+        let at = client.fetch_topic_offsets(topic, FetchOffset::ByTime(timestamp))?;
+        let earliest = client.fetch_topic_offsets(topic, FetchOffset::Earliest)?;
+        let latest = client.fetch_topic_offsets(topic, FetchOffset::Latest)?;
+        let bound = |offsets: &[PartitionOffset], partition: i32| {
+            offsets
+                .iter()
+                .find(|o| o.partition == partition)
+                .map_or(0, |o| o.offset)
+        };
+        Ok(at
+            .iter()
+            .map(|o| {
+                let earliest = bound(&earliest, o.partition);
+                let latest = bound(&latest, o.partition).max(earliest);
+                PartitionOffset {
+                    partition: o.partition,
+                    // No segment is that old, so everything retained is newer.
+                    offset: if o.offset < 0 {
+                        earliest
+                    } else {
+                        o.offset.clamp(earliest, latest)
+                    },
+                }
+            })
+            .collect())
+    }
+
+    /// The newest `n` messages of `topic`, read from `n` before the latest
+    /// offset of each partition, or its earliest if fewer are retained. A
+    /// consumer can't be positioned like that, so this fetches directly, as
+    /// `read_from_offset` does, and commits nothing.
+    pub fn read_tail(
+        &self,
+        topic: &str,
+        n: usize,
+    ) -> Result<Vec<ConsumedMessage>, KafkaClientError> {
+        let starts = {
+            let mut client = self.client.borrow_mut();
+            let earliest = client.fetch_topic_offsets(topic, FetchOffset::Earliest)?;
+            let latest = client.fetch_topic_offsets(topic, FetchOffset::Latest)?;
+            latest
+                .iter()
+                .map(|bound| {
+                    let earliest = earliest
+                        .iter()
+                        .find(|o| o.partition == bound.partition)
+                        .map_or(0, |o| o.offset);
+                    let start = bound.offset.saturating_sub(n as i64).max(earliest);
+                    (bound.partition, start, bound.offset)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut partitions = vec![];
+        for (partition, start, latest) in starts {
+            if start < latest {
+                partitions.push(self.read_from_offset(topic, partition, start, n)?);
+            }
+        }
+        // Without timestamps there is no telling which partition's messages
+        // are newer.
+        let keep = fair_shares(partitions.iter().map(Vec::len).collect(), n);
+        Ok(partitions
+            .into_iter()
+            .zip(keep)
+            .flat_map(|(messages, keep)| {
+                let skip = messages.len() - keep;
+                messages.into_iter().skip(skip)
+            })
+            .collect())
+    }
+
+    /// Polls `consumer` and hands each message to `f` until it returns false,
+    /// committing the offsets of the messages handed over if `commit` is set.
+    pub fn start_consumer(
+        &self,
+        mut consumer: Consumer,
+        commit: bool,
+        f: &mut dyn FnMut(ConsumedMessage) -> bool,
+    ) -> Result<(), KafkaClientError> {
+        let commit_consumed = |consumer: &mut Consumer| {
+            if commit {
+                consumer.commit_consumed()
+            } else {
+                Ok(())
+            }
+        };
         loop {
-            for ms in consumer.poll().unwrap().iter() {
+            for ms in consumer.poll()?.iter() {
                 for m in ms.messages() {
-                    let message = parse_message(m.value);
-                    f(message)
+                    let more = f(ConsumedMessage::from_kafka(
+                        ms.partition(),
+                        m,
+                        &self.decoder,
+                    ));
+                    consumer.consume_message(ms.topic(), ms.partition(), m.offset)?;
+                    if !more {
+                        commit_consumed(&mut consumer)?;
+                        return Ok(());
+                    }
                 }
-                consumer.consume_messageset(ms).unwrap();
             }
-            consumer.commit_consumed().unwrap();
+            commit_consumed(&mut consumer)?;
         }
     }
 
-    pub fn create_producer(&self) -> Producer {
-        return Producer::from_hosts(self.hosts.clone())
-            .with_ack_timeout(Duration::from_secs(10))
-            .with_required_acks(RequiredAcks::One)
-            .create()
-            .unwrap();
+    pub fn create_producer(&self) -> kafka::Result<Producer> {
+        let builder = Producer::from_hosts(self.hosts.clone())
+            .with_ack_timeout(self.producer.ack_timeout)
+            .with_required_acks(match self.producer.required_acks {
+                Acks::None => RequiredAcks::None,
+                Acks::One => RequiredAcks::One,
+                Acks::All => RequiredAcks::All,
+            })
+            .with_compression(match self.producer.compression {
+                Compression::None => kafka::client::Compression::NONE,
+                Compression::Gzip => kafka::client::Compression::GZIP,
+                Compression::Snappy => kafka::client::Compression::SNAPPY,
+            });
+        let builder = match self.security() {
+            Some(security) => builder.with_security(security),
+            None => builder,
+        };
+        builder.create()
     }
 
-    pub fn produce(&self, mut producer: Producer, topic: String, msg: String) {
-        let mut buf = String::with_capacity(2);
-        let _ = write!(&mut buf, "{}", msg);
-        producer
-            .send(&Record::from_value(&topic, buf.as_bytes()))
-            .unwrap();
-        buf.clear();
+    pub fn produce(
+        &self,
+        producer: &mut Producer,
+        topic: String,
+        msg: String,
+    ) -> kafka::Result<Option<Delivery>> {
+        self.produce_keyed(producer, &topic, "", &msg)
+    }
+
+    /// Sends `msg` with `key`, which decides its partition. An empty key is
+    /// sent as no key at all, so those messages are still spread round-robin
+    /// instead of all hashing to the same partition. Returns where the
+    /// message was written, unless the profile asks for no acknowledgement.
+    pub fn produce_keyed(
+        &self,
+        producer: &mut Producer,
+        topic: &str,
+        key: &str,
+        msg: &str,
+    ) -> kafka::Result<Option<Delivery>> {
+        let confirms = producer.send_all(&[record(topic, Some(key.as_bytes()), msg.as_bytes())])?;
+        match confirms.iter().flat_map(|c| &c.partition_confirms).next() {
+            Some(confirm) => match confirm.offset {
+                Ok(offset) => Ok(Some(Delivery {
+                    partition: confirm.partition,
+                    offset,
+                })),
+                Err(code) => Err(kafka::Error::Kafka(code)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Sends `msgs`, each a key and a value, to `topic` in one request, so
+    /// high-volume sends don't pay a round trip per message. Messages with
+    /// no key or an empty one are spread round-robin like `produce_keyed`.
+    /// Returns how many were sent, failing on the first rejected partition.
+    pub fn produce_batch(
+        &self,
+        producer: &mut Producer,
+        topic: &str,
+        msgs: Vec<(Option<Vec<u8>>, Vec<u8>)>,
+    ) -> kafka::Result<usize> {
+        let records = batch_records(topic, &msgs);
+        let confirms = producer.send_all(&records)?;
+        if let Some(code) = confirms
+            .iter()
+            .flat_map(|c| &c.partition_confirms)
+            .find_map(|confirm| confirm.offset.err())
+        {
+            return Err(kafka::Error::Kafka(code));
+        }
+        Ok(records.len())
     }
 }
 
+/// A record of `value` with `key` for `topic`. The kafka crate sends an empty
+/// key as no key at all, which leaves the partition to its round-robin.
+fn record<'a>(
+    topic: &'a str,
+    key: Option<&'a [u8]>,
+    value: &'a [u8],
+) -> Record<'a, &'a [u8], &'a [u8]> {
+    Record::from_key_value(topic, key.unwrap_or_default(), value)
+}
+
+/// A record for each key and value in `msgs`, in order.
+fn batch_records<'a>(
+    topic: &'a str,
+    msgs: &'a [(Option<Vec<u8>>, Vec<u8>)],
+) -> Vec<Record<'a, &'a [u8], &'a [u8]>> {
+    msgs.iter()
+        .map(|(key, value)| record(topic, key.as_deref(), value))
+        .collect()
+}
+
+fn tls_connector(tls: &TlsConfig) -> Result<SslConnector, KafkaClientError> {
+    let mut builder = SslConnector::builder(SslMethod::tls()).map_err(KafkaClientError::Tls)?;
+    if let Some(ca_cert) = &tls.ca_cert {
+        tls_file(ca_cert)?;
+        builder
+            .set_ca_file(ca_cert)
+            .map_err(|e| KafkaClientError::InvalidTlsFile(ca_cert.clone(), e))?;
+    }
+    if let Some(client_cert) = &tls.client_cert {
+        tls_file(client_cert)?;
+        builder
+            .set_certificate_chain_file(client_cert)
+            .map_err(|e| KafkaClientError::InvalidTlsFile(client_cert.clone(), e))?;
+    }
+    if let Some(client_key) = &tls.client_key {
+        tls_file(client_key)?;
+        builder
+            .set_private_key_file(client_key, SslFiletype::PEM)
+            .and_then(|_| builder.check_private_key())
+            .map_err(|e| KafkaClientError::InvalidTlsFile(client_key.clone(), e))?;
+    }
+    Ok(builder.build())
+}
+
+fn tls_file(path: &Path) -> Result<(), KafkaClientError> {
+    if path.is_file() {
+        Ok(())
+    } else {
+        Err(KafkaClientError::MissingTlsFile(path.to_path_buf()))
+    }
+}
+
+/// Decodes a message key, treating an empty key as absent.
+pub fn parse_key(key_bytes: &[u8]) -> Option<String> {
+    if key_bytes.is_empty() {
+        None
+    } else {
+        Some(parse_message(key_bytes))
+    }
+}
+
+/// How many of each partition's messages to keep, given how many each has,
+/// so that `n` are kept in all. The partitions with the most give way first,
+/// so one busy partition can't crowd out the rest.
+pub fn fair_shares(mut counts: Vec<usize>, n: usize) -> Vec<usize> {
+    while counts.iter().sum::<usize>() > n {
+        if let Some(most) = counts.iter_mut().max() {
+            *most -= 1;
+        }
+    }
+    counts
+}
+
+/// Decodes a payload for display. Bytes that aren't valid UTF-8 are rendered
+/// as `\xNN` escapes so binary payloads can't take down the UI.
 pub fn parse_message(message_bytes: &[u8]) -> String {
-    str::from_utf8(&message_bytes).unwrap().to_owned()
+    let mut message = String::with_capacity(message_bytes.len());
+    let mut rest = message_bytes;
+    loop {
+        match str::from_utf8(rest) {
+            Ok(valid) => {
+                message.push_str(valid);
+                return message;
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                message.push_str(str::from_utf8(valid).unwrap_or_default());
+                let bad = e.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..bad] {
+                    let _ = write!(message, "\\x{:02x}", byte);
+                }
+                rest = &invalid[bad..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(value: &[u8]) -> ConsumedMessage {
+        ConsumedMessage {
+            partition: 0,
+            offset: 0,
+            key: None,
+            value: parse_message(value),
+            raw: value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn valid_json_is_kept_and_pretty_printed() {
+        let message = message(r#"{"id":1,"name":"Zoë"}"#.as_bytes());
+        assert_eq!(message.value, r#"{"id":1,"name":"Zoë"}"#);
+        assert_eq!(
+            message.pretty_value().as_deref(),
+            Some("{\n  \"id\": 1,\n  \"name\": \"Zoë\"\n}")
+        );
+    }
+
+    #[test]
+    fn invalid_json_is_shown_as_text() {
+        let message = message(br#"{"id": 1"#);
+        assert_eq!(message.value, r#"{"id": 1"#);
+        assert_eq!(message.pretty_value(), None);
+    }
+
+    #[test]
+    fn invalid_utf8_is_escaped() {
+        assert_eq!(parse_message(b"ok\xff\xfe!"), "ok\\xff\\xfe!");
+        // A sequence cut short at the end is escaped too.
+        assert_eq!(parse_message(b"caf\xc3"), "caf\\xc3");
+    }
+
+    #[test]
+    fn exports_carry_the_raw_value() {
+        let exported = serde_json::to_value(message(b"ok\xff\xfe!")).unwrap();
+        assert_eq!(exported["value"], "ok\\xff\\xfe!");
+        let raw = exported["raw"].as_str().unwrap();
+        assert_eq!(base64::decode(raw).unwrap(), b"ok\xff\xfe!");
+    }
+
+    #[test]
+    fn key_filters() {
+        let keyed = |key: &str| ConsumedMessage {
+            key: Some(key.to_string()),
+            ..message(b"")
+        };
+        let unkeyed = message(b"");
+        let everything = KeyFilter::default();
+        assert!(keyed("tenant-1").key_matches(&everything));
+        assert!(unkeyed.key_matches(&everything));
+
+        let prefix = KeyFilter::new("tenant-").unwrap();
+        assert!(keyed("tenant-1").key_matches(&prefix));
+        assert!(!keyed("other-tenant-1").key_matches(&prefix));
+        assert!(!unkeyed.key_matches(&prefix));
+        assert_eq!(prefix.label(), "tenant-*");
+
+        let regex = KeyFilter::new("/^tenant-(1|2)$").unwrap();
+        assert!(keyed("tenant-2").key_matches(&regex));
+        assert!(!keyed("tenant-12").key_matches(&regex));
+        assert!(!unkeyed.key_matches(&regex));
+        assert_eq!(regex.label(), "/^tenant-(1|2)$");
+
+        assert!(matches!(
+            KeyFilter::new("/tenant-(1"),
+            Err(PatternError::UnclosedGroup)
+        ));
+    }
+
+    #[test]
+    fn empty_payloads() {
+        assert_eq!(parse_message(b""), "");
+        assert_eq!(message(b"").pretty_value(), None);
+        assert_eq!(parse_key(b""), None);
+    }
+
+    #[test]
+    fn records_carry_their_key() {
+        let keyed = record("orders", Some(b"user-1"), b"created");
+        assert_eq!(keyed.topic, "orders");
+        assert_eq!(keyed.key, b"user-1");
+        assert_eq!(keyed.value, b"created");
+        for key in [None, Some(&b""[..])] {
+            let unkeyed = record("orders", key, b"created");
+            assert!(unkeyed.key.is_empty());
+            assert_eq!(unkeyed.value, b"created");
+        }
+    }
+
+    /// What consuming `record` back would show.
+    fn receive(record: Record<&[u8], &[u8]>) -> ConsumedMessage {
+        let message = Message {
+            offset: 7,
+            key: record.key,
+            value: record.value,
+        };
+        ConsumedMessage::from_kafka(0, &message, &Decoder::new(None, None))
+    }
+
+    #[test]
+    fn values_round_trip() {
+        // "naïve café ✓ 🚀" and "kë", spelled out as UTF-8.
+        let value = b"na\xc3\xafve caf\xc3\xa9 \xe2\x9c\x93 \xf0\x9f\x9a\x80";
+        let received = receive(record("orders", Some(b"k\xc3\xab"), value));
+        assert_eq!(received.raw, value);
+        assert_eq!(received.value, "naïve café ✓ 🚀");
+        assert_eq!(received.key.as_deref(), Some("kë"));
+
+        // An emoji cut short keeps its bytes, and is escaped for display.
+        let received = receive(record("orders", None, b"\xf0\x9f\x9a"));
+        assert_eq!(received.raw, b"\xf0\x9f\x9a");
+        assert_eq!(received.value, "\\xf0\\x9f\\x9a");
+        assert_eq!(received.key, None);
+    }
+
+    /// A retry that doesn't wait between attempts.
+    fn retry(attempts: u32) -> Retry {
+        Retry {
+            backoff: Duration::ZERO,
+            ..Retry::new(attempts)
+        }
+    }
+
+    fn unreachable() -> KafkaClientError {
+        KafkaClientError::Kafka(kafka::Error::NoHostReachable)
+    }
+
+    #[test]
+    fn busy_partitions_give_way_first() {
+        assert_eq!(fair_shares(vec![100, 3, 0], 10), vec![7, 3, 0]);
+        assert_eq!(fair_shares(vec![10, 10], 5), vec![3, 2]);
+        assert_eq!(fair_shares(vec![1, 2], 10), vec![1, 2]);
+    }
+
+    #[test]
+    fn retry_succeeds_after_transient_failures() {
+        let mut calls = 0;
+        let mut retried = vec![];
+        let result = retry(3).run(
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err(unreachable())
+                } else {
+                    Ok(calls)
+                }
+            },
+            |retry| retried.push(retry.attempt()),
+        );
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(retried, [2, 3]);
+    }
+
+    #[test]
+    fn retry_gives_up_after_the_last_attempt() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry(3).run(
+            || {
+                calls += 1;
+                Err(unreachable())
+            },
+            |_| {},
+        );
+        assert!(matches!(
+            result,
+            Err(KafkaClientError::Kafka(kafka::Error::NoHostReachable))
+        ));
+        assert_eq!(calls, 3);
+
+        // Errors that won't go away aren't retried at all.
+        let mut calls = 0;
+        let result: Result<(), _> = retry(3).run(
+            || {
+                calls += 1;
+                Err(KafkaClientError::Invalid("no such topic".to_string()))
+            },
+            |_| {},
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn batches_keep_every_message() {
+        let msgs: Vec<_> = (0..1000)
+            .map(|i| {
+                let key = (i % 2 == 0).then(|| format!("key-{}", i).into_bytes());
+                (key, format!("value-{}", i).into_bytes())
+            })
+            .collect();
+        let records = batch_records("orders", &msgs);
+        assert_eq!(records.len(), msgs.len());
+        for (record, (key, value)) in records.iter().zip(&msgs) {
+            assert_eq!(record.topic, "orders");
+            assert_eq!(record.key, key.as_deref().unwrap_or_default());
+            assert_eq!(record.value, &value[..]);
+        }
+        assert!(batch_records("orders", &[]).is_empty());
+    }
 }
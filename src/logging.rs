@@ -0,0 +1,88 @@
+//! Opt-in diagnostics written to `~/.kafka/kafku.log`. The TUI owns the
+//! terminal, so nothing is ever logged to stdout or stderr.
+use crate::config;
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Set to a level such as `debug` to turn logging on.
+const LOG_ENV: &str = "KAFKU_LOG";
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error("{LOG_ENV} must be one of off, error, warn, info, debug or trace, not \"{0}\"")]
+    InvalidLevel(String),
+    #[error(transparent)]
+    Config(#[from] config::ConfigError),
+    #[error("could not open log file {0}: {1}")]
+    Open(PathBuf, io::Error),
+}
+
+struct FileLogger {
+    level: LevelFilter,
+    file: Mutex<File>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Ok(mut file) = self.file.lock() {
+            // A full disk shouldn't take the TUI down with it.
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Starts appending log records to `~/.kafka/kafku.log` when `KAFKU_LOG` is
+/// set, returning the file's path. Records from the kafka crate are included.
+pub fn init() -> Result<Option<PathBuf>, LogError> {
+    let level = match env::var(LOG_ENV) {
+        Ok(level) if !level.trim().is_empty() => level,
+        _ => return Ok(None),
+    };
+    let level: LevelFilter = level
+        .trim()
+        .parse()
+        .map_err(|_| LogError::InvalidLevel(level.clone()))?;
+    if level == LevelFilter::Off {
+        return Ok(None);
+    }
+    let dir = config::kafka_dir()?;
+    let path = dir.join("kafku.log");
+    let file = fs::create_dir_all(&dir)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+        .map_err(|e| LogError::Open(path.clone(), e))?;
+    let logger = FileLogger {
+        level,
+        file: Mutex::new(file),
+    };
+    // Only fails when a logger is already set, which init never does twice.
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+    Ok(Some(path))
+}
@@ -1,78 +1,449 @@
-use crate::{config::KafkaConfig, kafka_client::parse_message};
-use crate::kafka_client::SimpleKafkaClient;
-use chrono::prelude::*;
+use crate::admin::{GroupOffset, TopicConfig};
+use crate::cli::{Command, UsageError};
+use crate::config::TopicColumn;
+use crate::decode::Decoder;
+use crate::form::Form;
+use crate::kafka_client::{
+    fair_shares, KafkaClientError, KeyFilter, OffsetReset, ResetTarget, Retry, SimpleKafkaClient,
+};
+use crate::pattern::{PatternError, Regex};
+use crate::{config::KafkaConfig, kafka_client::ConsumedMessage};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use crossterm::{
-    event::{self, Event as CEvent, KeyCode},
+    cursor,
+    event::{self, Event as CEvent, KeyCode, KeyModifiers},
+    execute,
     terminal::{disable_raw_mode, enable_raw_mode},
 };
+use kafka::consumer::FetchOffset;
+use kafka::producer::Producer;
 use kafka_client::TopicData;
-use rand::{distributions::Alphanumeric, prelude::*};
+use pull::{PullEvent, PullSession};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, Write};
+use std::iter;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
-use std::{env, str};
-use thiserror::Error;
+use std::{env, process};
 use tui::{
-    backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    backend::{Backend, CrosstermBackend},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
     widgets::{
-        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs,
+        Block, BorderType, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table,
+        TableState, Tabs, Wrap,
     },
-    Terminal,
+    Frame, Terminal,
 };
 
+mod admin;
+mod base64;
+mod cli;
+mod clipboard;
 mod config;
+mod decode;
+mod form;
 mod kafka_client;
+mod logging;
+mod pattern;
+mod pull;
+
+const MESSAGES_PAGE: isize = 10;
+/// How many messages View last offers to read.
+const DEFAULT_TAIL: usize = 20;
+/// How many times its fair share of partition leaderships the busiest broker
+/// may hold before the Info tab warns about it.
+const LEADER_SKEW: f64 = 1.5;
+/// How long the result of an action stays in the status bar.
+const STATUS_TTL: Duration = Duration::from_secs(5);
+/// How long after the last typed letter a type-ahead jump starts over.
+const TYPE_AHEAD_IDLE: Duration = Duration::from_secs(1);
 
 enum Event<I> {
     Input(I),
     Tick,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct User {
-    id: usize,
-    name: String,
-    role: String,
-    age: usize,
-    created_at: DateTime<Utc>,
+/// An entry in the Messages pane: either a consumed message or feedback from
+/// an action such as producing.
+#[derive(Clone)]
+enum Line {
+    Message(ConsumedMessage),
+    Notice(String),
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Message(message) => message.fmt(f),
+            Line::Notice(notice) => f.write_str(notice),
+        }
+    }
+}
+
+/// The pane that Up/Down and other navigation keys act on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Focus {
+    Topics,
+    Detail,
+    Messages,
+}
+
+impl Focus {
+    fn next(self) -> Focus {
+        match self {
+            Focus::Topics => Focus::Detail,
+            Focus::Detail => Focus::Messages,
+            Focus::Messages => Focus::Topics,
+        }
+    }
+}
+
+/// A key and what it does. Bindings with a menu title are also listed in the
+/// menu, with the key underlined in the title.
+struct KeyBinding {
+    key: &'static str,
+    menu: Option<&'static str>,
+    action: &'static str,
+}
+
+impl KeyBinding {
+    const fn menu(key: &'static str, title: &'static str, action: &'static str) -> KeyBinding {
+        KeyBinding {
+            key,
+            menu: Some(title),
+            action,
+        }
+    }
+
+    const fn key(key: &'static str, action: &'static str) -> KeyBinding {
+        KeyBinding {
+            key,
+            menu: None,
+            action,
+        }
+    }
+}
+
+/// Every binding outside of dialogs and text input, in the order the menu
+/// and the help overlay list them.
+const KEYMAP: &[KeyBinding] = &[
+    KeyBinding::menu("t", "Topics", "Show the topics"),
+    KeyBinding::menu("s", "Send", "Send messages to the selected topic"),
+    KeyBinding::menu("g", "Groups", "Show the consumer groups"),
+    KeyBinding::menu("w", "Rewind group", "Reset the selected group's offsets"),
+    KeyBinding::menu("p", "Pull", "Pull messages, or stop following"),
+    KeyBinding::menu("o", "Offset", "Pull from the earliest or latest offset"),
+    KeyBinding::menu("a", "At offset", "Read from a partition and offset"),
+    KeyBinding::menu(
+        "b",
+        "Back to time",
+        "Read every partition from a date and time",
+    ),
+    KeyBinding::menu("v", "View last", "Read the newest messages of the topic"),
+    KeyBinding::menu("r", "Refresh", "Reload topics and offsets"),
+    KeyBinding::menu(
+        "m",
+        "Msg rate",
+        "Measure the selected topic's messages per second",
+    ),
+    KeyBinding::menu("n", "New topic", "Create a topic"),
+    KeyBinding::menu("d", "Delete", "Delete the selected topic"),
+    KeyBinding::menu("i", "Describe", "Show the selected topic's configs"),
+    KeyBinding::menu("l", "Leaders", "Show how many partitions each broker leads"),
+    KeyBinding::menu("f", "Find", "Search the messages"),
+    KeyBinding::menu(
+        "k",
+        "Key filter",
+        "Only show keys with a prefix, or matching a /regex",
+    ),
+    KeyBinding::menu("j", "JSON", "Pretty print JSON messages"),
+    KeyBinding::menu("h", "Hex", "Show the selected message's bytes as hex"),
+    KeyBinding::menu("x", "Exact", "Show exact offsets"),
+    KeyBinding::menu(
+        "z",
+        "Compact (z)",
+        "Keep only the latest message of each key",
+    ),
+    KeyBinding::menu("e", "Export", "Write the messages to a file"),
+    KeyBinding::menu("y", "Copy", "Copy the selected message"),
+    KeyBinding::menu("c", "Clear", "Clear the messages"),
+    KeyBinding::menu("P", "Profile", "Switch to another profile"),
+    KeyBinding::menu("q", "Quit", "Quit"),
+    KeyBinding::menu("?", "Help (?)", "Show this help"),
+    KeyBinding::key("u", "Undo Clear, until the next pull"),
+    KeyBinding::key("B", "Copy the brokers as host:port,host:port"),
+    KeyBinding::key("C", "Turn committing pulled offsets off or on"),
+    KeyBinding::key(
+        "/",
+        "Filter the topics by name, or by regex after another /",
+    ),
+    KeyBinding::key("n / N", "Jump to the next or previous match"),
+    KeyBinding::key(
+        "Alt+letters",
+        "Jump to the first topic starting with them, typed within a second",
+    ),
+    KeyBinding::key(
+        "Other keys",
+        "Jump the same way, such as digits and most capitals",
+    ),
+    KeyBinding::key("Tab", "Move to the next pane"),
+    KeyBinding::key("Up / Down", "Move within the focused pane"),
+    KeyBinding::key("PgUp / PgDn", "Page through messages or partitions"),
+    KeyBinding::key(
+        "Enter",
+        "Send the message (Send tab), or show the selected one in full",
+    ),
+    KeyBinding::key("Esc", "Clear the filter, or go back to Topics"),
+];
+
+/// A popup dialog and the action it performs once confirmed.
+enum Dialog {
+    CreateTopic(Form),
+    /// A yes/no prompt before deleting a topic.
+    ConfirmDelete(String),
+    /// Deleting a large topic, which requires typing its name to confirm.
+    TypedDelete(String, Form),
+    /// Quitting although pulled offsets couldn't be committed.
+    ConfirmQuit(String),
+    /// Reading a topic from a partition and offset of the user's choosing.
+    ReadFromOffset(String, Form),
+    /// Reading every partition of a topic from a date and time.
+    ReadFromTime(String, Form),
+    /// Reading the newest messages of a topic.
+    ReadLast(String, Form),
+    /// A topic's configuration, or why it couldn't be loaded, scrolled down
+    /// by the given number of lines.
+    TopicConfigs(String, Result<Vec<TopicConfig>, String>, u16),
+    /// Moving a consumer group's offsets, confirmed by typing the group name.
+    ResetOffsets(String, Form),
+    /// Choosing the key prefix pulled messages must have to be shown.
+    KeyFilter(Form),
+    /// The key bindings, scrolled down by the given number of lines.
+    Help(u16),
+    /// How many partitions each broker leads, busiest first.
+    Leaders(Vec<(String, usize)>),
+    /// The profiles of the config file, with the one to switch to selected.
+    SwitchProfile(Vec<String>, usize),
+    /// A message in full, wrapped, and scrolled down by the given number of
+    /// lines.
+    MessageDetail(ConsumedMessage, u16),
+}
+
+impl Dialog {
+    fn form_mut(&mut self) -> Option<&mut Form> {
+        match self {
+            Dialog::CreateTopic(form)
+            | Dialog::TypedDelete(_, form)
+            | Dialog::ReadFromOffset(_, form)
+            | Dialog::ReadFromTime(_, form)
+            | Dialog::ReadLast(_, form)
+            | Dialog::ResetOffsets(_, form)
+            | Dialog::KeyFilter(form) => Some(form),
+            Dialog::ConfirmDelete(_)
+            | Dialog::ConfirmQuit(_)
+            | Dialog::TopicConfigs(..)
+            | Dialog::Help(_)
+            | Dialog::Leaders(_)
+            | Dialog::SwitchProfile(..)
+            | Dialog::MessageDetail(..) => None,
+        }
+    }
+}
+
+/// The consumer groups listed in the Groups tab and the committed offsets of
+/// the selected one.
+#[derive(Default)]
+struct GroupsView {
+    groups: Vec<String>,
+    state: ListState,
+    offsets: Vec<GroupOffset>,
+    error: Option<String>,
+}
+
+impl GroupsView {
+    /// Reloads the group list, keeping the selected group if it still exists.
+    fn load(&mut self, client: &SimpleKafkaClient) {
+        let selected = self.selected().cloned();
+        match client.list_consumer_groups() {
+            Ok(groups) => {
+                self.groups = groups;
+                self.error = None;
+            }
+            Err(e) => {
+                self.groups.clear();
+                self.error = Some(format!("Could not list consumer groups: {}", e));
+            }
+        }
+        match self
+            .groups
+            .iter()
+            .position(|g| Some(g) == selected.as_ref())
+        {
+            Some(index) => self.state.select(Some(index)),
+            None => clamp_selection(&mut self.state, self.groups.len()),
+        }
+        self.load_offsets(client);
+    }
+
+    fn select(&mut self, client: &SimpleKafkaClient, delta: isize) {
+        scroll(&mut self.state, self.groups.len(), delta);
+        self.load_offsets(client);
+    }
+
+    fn selected(&self) -> Option<&String> {
+        self.state.selected().and_then(|i| self.groups.get(i))
+    }
+
+    fn load_offsets(&mut self, client: &SimpleKafkaClient) {
+        self.offsets = match self.selected().map(|group| client.group_offsets(group)) {
+            Some(Ok(offsets)) => {
+                self.error = None;
+                offsets
+            }
+            Some(Err(e)) => {
+                self.error = Some(format!("Could not load committed offsets: {}", e));
+                vec![]
+            }
+            None => vec![],
+        };
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 enum MenuItem {
     Topics,
+    Produce,
+    Consumers,
 }
 
 impl From<MenuItem> for usize {
     fn from(input: MenuItem) -> usize {
         match input {
             MenuItem::Topics => 0,
+            MenuItem::Produce => 1,
+            MenuItem::Consumers => 2,
         }
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    let kafka_config: KafkaConfig = config::get(args[1].to_string()).unwrap();
-
-    println!("Using host: {}", kafka_config.broker());
+    restore_terminal_on_panic();
+    match logging::init() {
+        Ok(Some(path)) => eprintln!("Logging to {}", path.display()),
+        Ok(None) => {}
+        Err(e) => eprintln!("Logging is off: {}", e),
+    }
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let config_file = match cli::take_config(&mut args) {
+        Ok(path) => path,
+        Err(e) => usage_error(e, None),
+    };
+    let config_file = config_file.as_deref();
+    let state = config::load_state();
+    let (profile, read_only, tick_rate_ms, no_commit) = match cli::parse(&args) {
+        Ok(Command::Init) => return init(config_file),
+        Ok(Command::Version) => {
+            version();
+            return Ok(());
+        }
+        Ok(Command::Tui {
+            profile,
+            read_only,
+            tick_rate_ms,
+            no_commit,
+        }) => match profile
+            .or_else(|| default_profile(config_file))
+            .or_else(|| state.profile.clone())
+        {
+            Some(profile) => (profile, read_only, tick_rate_ms, no_commit),
+            None => usage_error(UsageError::MissingProfile, config_file),
+        },
+        Ok(Command::Topics { profile, json }) => {
+            return topics(load_profile(config_file, profile), json)
+        }
+        Ok(Command::Lag {
+            profile,
+            group,
+            json,
+            threshold,
+        }) => return lag(load_profile(config_file, profile), &group, json, threshold),
+        Ok(Command::Consume {
+            profile,
+            topic,
+            max,
+            from,
+            key_filter,
+            no_commit,
+        }) => {
+            let kafka_config = load_profile(config_file, profile);
+            return consume(
+                kafka_config,
+                &topic,
+                max,
+                from,
+                key_filter.as_deref(),
+                !no_commit,
+            );
+        }
+        Ok(Command::Produce {
+            profile,
+            topic,
+            key_separator,
+        }) => {
+            let kafka_config = load_profile(config_file, profile);
+            return produce(kafka_config, &topic, key_separator.as_deref());
+        }
+        Ok(Command::Replay {
+            profile,
+            topic,
+            file,
+            rate,
+        }) => return replay(load_profile(config_file, profile), &topic, &file, rate),
+        Err(e) => usage_error(e, config_file),
+    };
+    let mut kafka_config = load_profile(config_file, profile);
+    // --read-only also holds for profiles switched to later.
+    let read_only_flag = read_only;
+    let mut read_only = read_only_flag || kafka_config.read_only();
+    // Only remembered for the profile it was selected in.
+    let mut remembered_topic = match state.profile {
+        Some(profile) if &profile == kafka_config.name() => state.topic,
+        _ => None,
+    };
 
-    let kafka_hosts: Vec<String> = vec![kafka_config.broker().to_string()];
-    let client = SimpleKafkaClient {
-        hosts: kafka_hosts.clone(),
+    let source = if config::broker_override().is_some() {
+        " (from KAFKU_BROKER)"
+    } else {
+        ""
     };
+    println!(
+        "Using hosts: {}{}",
+        kafka_config.broker().join(", "),
+        source
+    );
 
-    let topic_list = client.list_topic_details();
+    let mut client = connect(&kafka_config);
+
+    let mut topic_list: Vec<TopicData> = vec![];
+    let mut summary = client.cluster_summary();
+    let mut connection_error: Option<String> = None;
+    // Filled in as the checks each refresh starts answer; hosts without an
+    // entry haven't been checked.
+    let mut broker_health: Vec<(String, bool)> = vec![];
+    let mut health_checks: Option<mpsc::Receiver<(String, bool)>> = None;
 
     enable_raw_mode().expect("can run in raw mode");
 
     let (tx, rx) = mpsc::channel();
-    let tick_rate = Duration::from_millis(200);
+    let tick_rate = tick_rate_ms.map_or_else(|| kafka_config.tick_rate(), Duration::from_millis);
     thread::spawn(move || {
         let mut last_tick = Instant::now();
         loop {
@@ -86,10 +457,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick) {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
@@ -99,13 +468,148 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let menu_titles = vec!["Topics", "Pull", "Clear", "Quit"];
+    let menu_titles: Vec<_> = KEYMAP
+        .iter()
+        .filter_map(|binding| binding.menu.map(|title| (title, binding.key)))
+        .collect();
     let mut active_menu_item = MenuItem::Topics;
     let mut topic_list_state = ListState::default();
-    let mut msgs: Vec<String> = vec![];
+    // A selected row limits Pull to that partition; none pulls all of them.
+    let mut detail_state = TableState::default();
+    // Oldest first, so dropping the oldest lines is cheap at any size.
+    let mut msgs: VecDeque<Line> = VecDeque::new();
+    let mut msgs_list_state = ListState::default();
+    // The topic the last messages were read from, named in the pane's title.
+    let mut msgs_topic: Option<String> = None;
+    let mut focus = Focus::Topics;
+    let mut filter = TopicFilter::default();
+    let mut filtering = false;
+    // The letters typed to jump to a topic, and when the last one was.
+    let mut type_ahead = String::new();
+    let mut type_ahead_at = Instant::now();
+    let mut search = String::new();
+    // Whether old messages were dropped to stay under the configured maximum.
+    let mut trimmed = false;
+    let mut searching = false;
+    // Starts out true so the initial metadata load happens inside the TUI.
+    let mut refreshing = true;
+    // Set to measure the selected topic's message rate after the next draw.
+    let mut measuring = false;
+    let mut rate: Option<(String, Result<f64, String>)> = None;
+    let mut retry = Retry::new(kafka_config.metadata_attempts());
+    // The result of the last action, cleared on the first tick after STATUS_TTL.
+    let mut status: Option<(String, Instant)> = None;
+    let mut dialog: Option<Dialog> = None;
+    let mut session: Option<PullSession> = None;
+    // While following a topic from Latest, how many messages arrived so far.
+    let mut live: Option<usize> = None;
+    // What Clear removed, with the topic and trimmed flag that went with it,
+    // restored by u until the next pull.
+    let mut last_cleared: Option<(VecDeque<Line>, Option<String>, bool)> = None;
+    // Whether only the latest message of each key is kept, as log compaction
+    // would, which suits compacted topics.
+    let mut compact = false;
+    // Whether pulls move the group's offsets; off to peek at a topic without
+    // disturbing consumers that share the group.
+    let mut commit = !no_commit;
+    // Pulled messages whose key doesn't match this are dropped.
+    let mut key_filter = KeyFilter::default();
+    let mut quit = false;
+    // Lets profiles such as production stand out from the others.
+    let mut accent = kafka_config.accent_color();
+    let mut produce_form = Form::new("Produce", &["Key", "Value"]);
+    produce_form.active = 1;
+    let mut fetch_offset = FetchOffset::Earliest;
+    let mut pretty_json = false;
+    let mut hex = false;
+    // Offsets are grouped by thousands unless exact ones are wanted for copying.
+    let mut exact_offsets = false;
+    let mut topic_columns = TopicColumns::new(kafka_config.topic_columns());
+    let mut groups = GroupsView::default();
     topic_list_state.select(Some(0));
 
     loop {
+        while let Some(event) = session.as_mut().and_then(PullSession::try_event) {
+            match event {
+                PullEvent::Pulled(pulled, full) => {
+                    let pulled: Vec<_> = pulled
+                        .into_iter()
+                        .filter(|message| message.key_matches(&key_filter))
+                        .collect();
+                    if let Some(received) = live.as_mut() {
+                        *received += pulled.len();
+                    }
+                    if !pulled.is_empty() {
+                        msgs_topic = session.as_ref().map(|open| open.topic.clone());
+                    }
+                    msgs.extend(pulled.into_iter().map(Line::Message));
+                    // A full pull left messages behind that the consumer has
+                    // already fetched, so start over from the committed offsets.
+                    if full {
+                        if let Some(previous) = session.take() {
+                            let topic = previous.topic.clone();
+                            if let Err(e) = previous.close() {
+                                msgs.push_back(Line::Notice(format!(
+                                    "Could not commit the offsets pulled from {}: {}",
+                                    topic, e
+                                )));
+                            }
+                        }
+                    }
+                }
+                PullEvent::Failed(e) => {
+                    let topic = session.take().map(|open| open.topic).unwrap_or_default();
+                    msgs.push_back(Line::Notice(format!(
+                        "Could not pull from {}: {}",
+                        topic, e
+                    )));
+                    live = None;
+                }
+            }
+        }
+        if compact && compact_lines(&mut msgs) && msgs_list_state.selected().is_some() {
+            clamp_selection(&mut msgs_list_state, msgs.len());
+        }
+        if cap_messages(&mut msgs, &mut msgs_list_state, kafka_config.max_messages()) > 0
+            && !trimmed
+        {
+            trimmed = true;
+            status = Some((
+                format!(
+                    "Buffer full at {} lines, dropping the oldest messages",
+                    kafka_config.max_messages()
+                ),
+                Instant::now(),
+            ));
+        }
+        let visible_topics = filter_topics(&topic_list, &filter);
+        let pull_partition =
+            get_selected_topic(&topic_list_state, &visible_topics).and_then(|topic| {
+                let selected = detail_state.selected()?;
+                topic.partitions.get(selected).map(|p| p.id)
+            });
+        let mut notes = vec![];
+        if trimmed {
+            notes.push(format!(
+                "buffer full, oldest dropped past {}",
+                kafka_config.max_messages()
+            ));
+        }
+        if let Some(partition) = pull_partition {
+            notes.push(format!("partition {}", partition));
+        }
+        if !key_filter.is_empty() {
+            notes.push(format!("key {}", key_filter.label()));
+        }
+        if compact {
+            notes.push("latest per key".to_string());
+        }
+        if let Some(received) = live {
+            notes.push(format!("live, {} new", received));
+        }
+        if live.is_none() && session.as_ref().is_some_and(PullSession::is_busy) {
+            notes.push("pulling".to_string());
+        }
         terminal.draw(|rect| {
             let size = rect.size();
             let chunks = Layout::default()
@@ -116,6 +620,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Constraint::Length(3),
                         Constraint::Length(3),
                         Constraint::Min(2),
+                        Constraint::Length(1),
                         Constraint::Length(3),
                     ]
                     .as_ref(),
@@ -135,13 +640,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             let menu = menu_titles
                 .iter()
-                .map(|t| {
-                    let (first, rest) = t.split_at(1);
+                .map(|(title, key)| {
+                    let at = title.to_lowercase().find(key).unwrap_or(0);
+                    let (first, rest) = title.split_at(at);
+                    let (hotkey, rest) = rest.split_at(1);
                     Spans::from(vec![
+                        Span::styled(first, Style::default().fg(Color::White)),
                         Span::styled(
-                            first,
+                            hotkey,
                             Style::default()
-                                .fg(Color::Yellow)
+                                .fg(accent)
                                 .add_modifier(Modifier::UNDERLINED),
                         ),
                         Span::styled(rest, Style::default().fg(Color::White)),
@@ -153,31 +661,112 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .select(active_menu_item.into())
                 .block(Block::default().title("Menu").borders(Borders::ALL))
                 .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().fg(Color::Yellow))
+                .highlight_style(Style::default().fg(accent))
                 .divider(Span::raw("|"));
 
-            let hosts = Spans::from(vec![Span::styled(
-                broker_info_label(client.list_brokers()),
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::UNDERLINED),
-            )]);
+            let hosts = broker_info(&summary.brokers, &broker_health);
 
             let topic_num = Spans::from(vec![Span::styled(
-                num_topics_label(client.list_topics().len()),
+                num_topics_label(summary.topic_count),
                 Style::default()
                     .fg(Color::Yellow)
                     .add_modifier(Modifier::UNDERLINED),
             )]);
 
-            let info_tab = Tabs::new(vec![hosts, topic_num])
+            let offset_mode = Spans::from(vec![Span::styled(
+                fetch_offset_label(fetch_offset),
+                Style::default().fg(Color::Yellow),
+            )]);
+
+            let group = Spans::from(vec![Span::styled(
+                group_label(&kafka_config.group(), fetch_offset),
+                Style::default().fg(Color::Yellow),
+            )]);
+
+            let mut info = vec![hosts, topic_num, offset_mode, group];
+            if read_only {
+                info.push(Spans::from(Span::styled(
+                    "Read-only",
+                    Style::default().fg(accent).add_modifier(Modifier::BOLD),
+                )));
+            }
+            if !commit {
+                info.push(Spans::from(Span::styled(
+                    "Not committing offsets",
+                    Style::default().fg(accent).add_modifier(Modifier::BOLD),
+                )));
+            }
+            let leaderless = leaderless_partitions(&topic_list);
+            if leaderless > 0 {
+                info.push(Spans::from(Span::styled(
+                    match leaderless {
+                        1 => "1 partition without a leader".to_string(),
+                        n => format!("{} partitions without a leader", n),
+                    },
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )));
+            }
+            if let Some((host, leads)) = leader_skew(&leader_counts(&topic_list)) {
+                info.push(Spans::from(Span::styled(
+                    format!("Leaders skewed: {} leads {}", host, leads),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )));
+            }
+            if measuring {
+                info.push(Spans::from(Span::styled(
+                    "Measuring…",
+                    Style::default().fg(Color::LightCyan),
+                )));
+            } else if let Some((topic, rate)) = &rate {
+                info.push(rate_label(topic, rate));
+            }
+            if refreshing {
+                info.push(Spans::from(Span::styled(
+                    match retry.attempt() {
+                        1 => "Refreshing…".to_string(),
+                        attempt => format!("Retrying (attempt {}/{})…", attempt, retry.attempts()),
+                    },
+                    Style::default().fg(Color::LightCyan),
+                )));
+            }
+
+            let info_tab = Tabs::new(info)
                 .block(Block::default().title("Info").borders(Borders::ALL))
                 .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().fg(Color::Yellow))
+                // Tabs always highlights one entry; a plain style keeps the
+                // broker colors of the first one intact.
+                .highlight_style(Style::default())
                 .divider(Span::raw("|"));
 
+            let mut status_line = vec![];
+            // Stays on every tab, so a pull is never mistaken for one that
+            // moves the group's offsets.
+            if !commit {
+                status_line.push(Span::styled(
+                    "[not committing] ",
+                    Style::default().fg(accent).add_modifier(Modifier::BOLD),
+                ));
+            }
+            if let Some((text, _)) = &status {
+                status_line.push(Span::raw(text.clone()));
+            }
+            let status_bar = Paragraph::new(Spans::from(status_line))
+                .style(Style::default().fg(Color::LightCyan));
+
             rect.render_widget(tabs, chunks[0]);
             rect.render_widget(info_tab, chunks[1]);
+            rect.render_widget(status_bar, chunks[3]);
+            if let Some(error) = &connection_error {
+                let screen = connection_error_block(error, &summary.brokers, accent);
+                rect.render_widget(screen, chunks[2]);
+                if let Some(dialog) = &dialog {
+                    render_dialog(rect, dialog, chunks[2], accent);
+                }
+                rect.render_widget(copyright, chunks[4]);
+                return;
+            }
             match active_menu_item {
                 MenuItem::Topics => {
                     let topics_chunks = Layout::default()
@@ -191,43 +780,829 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             .as_ref(),
                         )
                         .split(chunks[2]);
-                    let (left, right) = render_topics(&topic_list_state, topic_list.clone());
-                    let messages = messages_block(msgs.clone());
+                    let (left, right) = render_topics(
+                        &topic_list_state,
+                        &visible_topics,
+                        &topics_title(&filter, filtering),
+                        focus,
+                        &topic_columns,
+                        exact_offsets,
+                        detail_state.selected(),
+                        accent,
+                    );
+                    let messages = messages_block(
+                        &msgs,
+                        msgs_topic.as_deref(),
+                        focus == Focus::Messages,
+                        pretty_json,
+                        hex.then(|| msgs_list_state.selected()).flatten(),
+                        &search,
+                        searching,
+                        &notes,
+                        accent,
+                    );
                     rect.render_stateful_widget(left, topics_chunks[0], &mut topic_list_state);
-                    rect.render_widget(right, topics_chunks[1]);
-                    rect.render_widget(messages, topics_chunks[2]);
+                    rect.render_stateful_widget(right, topics_chunks[1], &mut detail_state);
+                    rect.render_stateful_widget(messages, topics_chunks[2], &mut msgs_list_state);
+                }
+                MenuItem::Produce => {
+                    let produce_chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [
+                                Constraint::Percentage(20),
+                                Constraint::Percentage(40),
+                                Constraint::Percentage(40),
+                            ]
+                            .as_ref(),
+                        )
+                        .split(chunks[2]);
+                    let (left, _) = render_topics(
+                        &topic_list_state,
+                        &visible_topics,
+                        &topics_title(&filter, filtering),
+                        focus,
+                        &topic_columns,
+                        exact_offsets,
+                        detail_state.selected(),
+                        accent,
+                    );
+                    let selected = get_selected_topic(&topic_list_state, &visible_topics)
+                        .map(|t| t.name)
+                        .unwrap_or_default();
+                    let input = produce_block(&produce_form, &selected, accent);
+                    let messages = messages_block(
+                        &msgs,
+                        msgs_topic.as_deref(),
+                        focus == Focus::Messages,
+                        pretty_json,
+                        hex.then(|| msgs_list_state.selected()).flatten(),
+                        &search,
+                        searching,
+                        &notes,
+                        accent,
+                    );
+                    rect.render_stateful_widget(left, produce_chunks[0], &mut topic_list_state);
+                    rect.render_widget(input, produce_chunks[1]);
+                    rect.render_stateful_widget(messages, produce_chunks[2], &mut msgs_list_state);
+                }
+                MenuItem::Consumers => {
+                    let groups_chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Percentage(20), Constraint::Percentage(80)].as_ref(),
+                        )
+                        .split(chunks[2]);
+                    let list = render_groups(&groups, focus == Focus::Topics, accent);
+                    rect.render_stateful_widget(list, groups_chunks[0], &mut groups.state);
+                    match &groups.error {
+                        Some(error) => rect.render_widget(
+                            Paragraph::new(error.clone())
+                                .wrap(Wrap { trim: false })
+                                .block(pane_block("Committed offsets".to_string(), false, accent)),
+                            groups_chunks[1],
+                        ),
+                        None => rect.render_widget(
+                            render_group_offsets(&groups.offsets, &topic_list, accent),
+                            groups_chunks[1],
+                        ),
+                    }
                 }
             }
-            rect.render_widget(copyright, chunks[3]);
+            if let Some(dialog) = &dialog {
+                render_dialog(rect, dialog, chunks[2], accent);
+            }
+            rect.render_widget(copyright, chunks[4]);
         })?;
 
+        // Measured after drawing, like refreshing, to show that it's underway.
+        if measuring {
+            if let Some(topic) = get_selected_topic(&topic_list_state, &visible_topics) {
+                let sampled = client.sample_topic_rate(&topic.name, RATE_SAMPLE_INTERVAL);
+                rate = Some((topic.name, sampled.map_err(|e| e.to_string())));
+            }
+            measuring = false;
+            continue;
+        }
+
+        // Refresh after drawing so the indicator is on screen while it runs.
+        if refreshing {
+            retry.backoff();
+            match client.refresh(&kafka_config.group()) {
+                // Redraws with the attempt shown before trying again.
+                Err(e) if retry.retry(&e) => continue,
+                result => connection_error = result.err().map(|e| e.to_string()),
+            }
+            retry = Retry::new(kafka_config.metadata_attempts());
+            topic_list = client.list_topic_details();
+            summary = client.cluster_summary();
+            // Hosts keep their last answer until the new check's comes in.
+            broker_health.retain(|(host, _)| summary.brokers.contains(host));
+            health_checks = Some(client.check_brokers(&summary.brokers));
+            reselect_topic(&mut topic_list_state, &visible_topics, &topic_list, &filter);
+            if let Some(topic) = remembered_topic.take() {
+                // Falls back to the first topic if the remembered one is gone.
+                if let Some(index) = topic_list.iter().position(|t| t.name == topic) {
+                    topic_list_state.select(Some(index));
+                }
+            }
+            // The selected topic may have lost partitions.
+            let partitions =
+                get_selected_topic(&topic_list_state, &filter_topics(&topic_list, &filter))
+                    .map_or(0, |topic| topic.partitions.len());
+            select_partition(&mut detail_state, partitions, 0);
+            if let MenuItem::Consumers = active_menu_item {
+                groups.load(&client);
+            }
+            refreshing = false;
+            continue;
+        }
+
         match rx.recv()? {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('q') => {
-                    disable_raw_mode()?;
-                    terminal.show_cursor()?;
-                    break;
-                }
-                KeyCode::Char('t') => active_menu_item = MenuItem::Topics,
-                KeyCode::Char('c') =>  {
-                    msgs.clear();
+            Event::Input(event) if dialog.is_some() => {
+                match (dialog.take().expect("checked by the guard"), event.code) {
+                    (_, KeyCode::Esc)
+                    | (Dialog::ConfirmDelete(_), KeyCode::Char('n'))
+                    | (Dialog::ConfirmQuit(_), KeyCode::Char('n')) => {}
+                    (Dialog::ConfirmQuit(_), KeyCode::Char('y')) => quit = true,
+                    (Dialog::TopicConfigs(topic, configs, scroll), KeyCode::Down) => {
+                        dialog = Some(Dialog::TopicConfigs(
+                            topic,
+                            configs,
+                            scroll.saturating_add(1),
+                        ));
+                    }
+                    (Dialog::TopicConfigs(topic, configs, scroll), KeyCode::Up) => {
+                        let scroll = scroll.saturating_sub(1);
+                        dialog = Some(Dialog::TopicConfigs(topic, configs, scroll));
+                    }
+                    (Dialog::TopicConfigs(..), _) => {}
+                    (Dialog::MessageDetail(message, scroll), KeyCode::Down) => {
+                        dialog = Some(Dialog::MessageDetail(message, scroll.saturating_add(1)));
+                    }
+                    (Dialog::MessageDetail(message, scroll), KeyCode::Up) => {
+                        let scroll = scroll.saturating_sub(1);
+                        dialog = Some(Dialog::MessageDetail(message, scroll));
+                    }
+                    (Dialog::MessageDetail(message, scroll), KeyCode::PageDown) => {
+                        let scroll = scroll.saturating_add(MESSAGES_PAGE as u16);
+                        dialog = Some(Dialog::MessageDetail(message, scroll));
+                    }
+                    (Dialog::MessageDetail(message, scroll), KeyCode::PageUp) => {
+                        let scroll = scroll.saturating_sub(MESSAGES_PAGE as u16);
+                        dialog = Some(Dialog::MessageDetail(message, scroll));
+                    }
+                    (Dialog::MessageDetail(..), _) => {}
+                    (Dialog::Help(scroll), KeyCode::Down) => {
+                        dialog = Some(Dialog::Help(scroll.saturating_add(1)));
+                    }
+                    (Dialog::Help(scroll), KeyCode::Up) => {
+                        dialog = Some(Dialog::Help(scroll.saturating_sub(1)));
+                    }
+                    (Dialog::Help(_), _) | (Dialog::Leaders(_), _) => {}
+                    (Dialog::SwitchProfile(names, selected), KeyCode::Down) => {
+                        let selected = (selected + 1).min(names.len().saturating_sub(1));
+                        dialog = Some(Dialog::SwitchProfile(names, selected));
+                    }
+                    (Dialog::SwitchProfile(names, selected), KeyCode::Up) => {
+                        dialog = Some(Dialog::SwitchProfile(names, selected.saturating_sub(1)));
+                    }
+                    (Dialog::SwitchProfile(names, selected), KeyCode::Enter) => {
+                        let switched = names
+                            .get(selected)
+                            .ok_or_else(|| "No profile to switch to".to_string())
+                            .and_then(|name| try_load_profile(config_file, name.clone()))
+                            .and_then(|config| {
+                                let connected = new_client(&config).map_err(|e| e.to_string())?;
+                                Ok((config, connected))
+                            });
+                        match switched {
+                            Ok((config, connected)) => {
+                                // Commits what was pulled from the old cluster.
+                                if let Some(previous) = session.take() {
+                                    let topic = previous.topic.clone();
+                                    if let Err(e) = previous.close() {
+                                        msgs.push_back(Line::Notice(format!(
+                                            "Could not commit the offsets pulled from {}: {}",
+                                            topic, e
+                                        )));
+                                    }
+                                }
+                                live = None;
+                                kafka_config = config;
+                                client = connected;
+                                summary = client.cluster_summary();
+                                read_only = read_only_flag || kafka_config.read_only();
+                                accent = kafka_config.accent_color();
+                                topic_columns = TopicColumns::new(kafka_config.topic_columns());
+                                retry = Retry::new(kafka_config.metadata_attempts());
+                                topic_list.clear();
+                                broker_health.clear();
+                                health_checks = None;
+                                connection_error = None;
+                                filter.clear();
+                                topic_list_state.select(Some(0));
+                                detail_state.select(None);
+                                groups = GroupsView::default();
+                                rate = None;
+                                msgs_topic = None;
+                                status = Some((
+                                    format!("Switched to profile {}", kafka_config.name()),
+                                    Instant::now(),
+                                ));
+                                refreshing = true;
+                            }
+                            Err(e) => status = Some((e, Instant::now())),
+                        }
+                    }
+                    (Dialog::SwitchProfile(..), _) => {}
+                    (Dialog::CreateTopic(mut form), KeyCode::Enter) => {
+                        if form.next_field() {
+                            dialog = Some(Dialog::CreateTopic(form));
+                        } else {
+                            match create_topic(&client, &form) {
+                                Ok(name) => {
+                                    msgs.push_back(Line::Notice(format!("Created topic {}", name)));
+                                    refreshing = true;
+                                }
+                                Err(e) => {
+                                    status = Some((
+                                        format!("Could not create topic: {}", e),
+                                        Instant::now(),
+                                    ));
+                                    dialog = Some(Dialog::CreateTopic(form));
+                                }
+                            }
+                        }
+                    }
+                    (Dialog::ReadFromOffset(topic, mut form), KeyCode::Enter) => {
+                        if form.next_field() {
+                            dialog = Some(Dialog::ReadFromOffset(topic, form));
+                        } else {
+                            let limit = kafka_config.max_messages();
+                            match read_from_offset(&client, &topic, &form, limit) {
+                                Ok(read) if read.is_empty() => msgs.push_back(Line::Notice(
+                                    format!("No messages in {} from that offset yet", topic),
+                                )),
+                                Ok(read) => {
+                                    last_cleared = None;
+                                    msgs.extend(
+                                        read.into_iter()
+                                            .filter(|message| message.key_matches(&key_filter))
+                                            .map(Line::Message),
+                                    );
+                                    msgs_topic = Some(topic);
+                                }
+                                Err(e) => {
+                                    status = Some((
+                                        format!("Could not read {}: {}", topic, e),
+                                        Instant::now(),
+                                    ));
+                                    dialog = Some(Dialog::ReadFromOffset(topic, form));
+                                }
+                            }
+                        }
+                    }
+                    (Dialog::ReadFromTime(topic, form), KeyCode::Enter) => {
+                        let limit = kafka_config.max_messages();
+                        match read_from_time(&client, &topic, &form, limit) {
+                            Ok(read) if read.is_empty() => msgs.push_back(Line::Notice(format!(
+                                "No messages in {} since {}",
+                                topic,
+                                form.value(0)
+                            ))),
+                            Ok(read) => {
+                                last_cleared = None;
+                                msgs.extend(
+                                    read.into_iter()
+                                        .filter(|message| message.key_matches(&key_filter))
+                                        .map(Line::Message),
+                                );
+                                msgs_topic = Some(topic);
+                            }
+                            Err(e) => {
+                                status = Some((
+                                    format!("Could not read {}: {}", topic, e),
+                                    Instant::now(),
+                                ));
+                                dialog = Some(Dialog::ReadFromTime(topic, form));
+                            }
+                        }
+                    }
+                    (Dialog::ReadLast(topic, form), KeyCode::Enter) => {
+                        let limit = kafka_config.max_messages();
+                        match read_last(&client, &topic, &form, limit) {
+                            Ok(read) if read.is_empty() => {
+                                msgs.push_back(Line::Notice(format!("No messages in {}", topic)))
+                            }
+                            Ok(read) => {
+                                last_cleared = None;
+                                msgs.extend(
+                                    read.into_iter()
+                                        .filter(|message| message.key_matches(&key_filter))
+                                        .map(Line::Message),
+                                );
+                                msgs_topic = Some(topic);
+                            }
+                            Err(e) => {
+                                status = Some((
+                                    format!("Could not read {}: {}", topic, e),
+                                    Instant::now(),
+                                ));
+                                dialog = Some(Dialog::ReadLast(topic, form));
+                            }
+                        }
+                    }
+                    (Dialog::KeyFilter(form), KeyCode::Enter) => {
+                        match KeyFilter::new(form.value(0)) {
+                            Ok(filter) => key_filter = filter,
+                            Err(e) => {
+                                status = Some((format!("Invalid regex: {}", e), Instant::now()));
+                                dialog = Some(Dialog::KeyFilter(form));
+                            }
+                        }
+                    }
+                    (Dialog::ResetOffsets(group, mut form), KeyCode::Enter) => {
+                        if form.next_field() {
+                            dialog = Some(Dialog::ResetOffsets(group, form));
+                        } else if form.value(2) != group {
+                            status = Some((
+                                format!("Type {} exactly to reset its offsets", group),
+                                Instant::now(),
+                            ));
+                            dialog = Some(Dialog::ResetOffsets(group, form));
+                        } else {
+                            match reset_offsets(&client, &group, &form) {
+                                Ok(resets) => {
+                                    for reset in resets {
+                                        msgs.push_back(Line::Notice(format!(
+                                            "Reset {} on {} partition {}: {} -> {}",
+                                            group,
+                                            form.value(0),
+                                            reset.partition,
+                                            reset
+                                                .before
+                                                .map(|offset| offset.to_string())
+                                                .unwrap_or_else(|| "none".to_string()),
+                                            reset.after
+                                        )));
+                                    }
+                                    groups.load_offsets(&client);
+                                }
+                                Err(e) => {
+                                    status = Some((
+                                        format!("Could not reset {}: {}", group, e),
+                                        Instant::now(),
+                                    ));
+                                    dialog = Some(Dialog::ResetOffsets(group, form));
+                                }
+                            }
+                        }
+                    }
+                    (Dialog::TypedDelete(topic, form), KeyCode::Enter)
+                        if form.value(0) != topic =>
+                    {
+                        status = Some((
+                            format!("Type {} exactly to delete it", topic),
+                            Instant::now(),
+                        ));
+                        dialog = Some(Dialog::TypedDelete(topic, form));
+                    }
+                    (Dialog::ConfirmDelete(topic), KeyCode::Char('y'))
+                    | (Dialog::TypedDelete(topic, _), KeyCode::Enter) => {
+                        match client.delete_topic(&topic) {
+                            Ok(()) => {
+                                msgs.push_back(Line::Notice(format!("Deleted topic {}", topic)));
+                                topic_list.retain(|t| t.name != topic);
+                                let remaining = filter_topics(&topic_list, &filter).len();
+                                topic_list_state.select(match topic_list_state.selected() {
+                                    _ if remaining == 0 => None,
+                                    Some(selected) => Some(selected.min(remaining - 1)),
+                                    None => Some(0),
+                                });
+                            }
+                            Err(e) => msgs.push_back(Line::Notice(format!(
+                                "Could not delete {}: {}",
+                                topic, e
+                            ))),
+                        }
+                    }
+                    (mut open, code) => {
+                        if let Some(form) = open.form_mut() {
+                            match code {
+                                KeyCode::Tab => form.cycle(),
+                                KeyCode::Backspace => form.backspace(),
+                                KeyCode::Char(c) => form.input(c),
+                                _ => {}
+                            }
+                        }
+                        dialog = Some(open);
+                    }
+                }
+            }
+            Event::Input(event) => match (active_menu_item, event.code) {
+                (_, KeyCode::Enter) if searching => {
+                    searching = false;
+                    jump_to_match(&mut msgs_list_state, &msgs, &search, true);
+                }
+                (_, KeyCode::Esc) if searching => {
+                    searching = false;
+                    search.clear();
+                }
+                (_, KeyCode::Backspace) if searching => {
+                    search.pop();
+                }
+                (_, KeyCode::Char(c)) if searching => search.push(c),
+                (_, KeyCode::Enter) if filtering => filtering = false,
+                (_, KeyCode::Esc) if filtering => {
+                    filtering = false;
+                    filter.clear();
+                    reselect_topic(&mut topic_list_state, &visible_topics, &topic_list, &filter);
+                }
+                (_, KeyCode::Backspace) if filtering => {
+                    filter.pop();
+                    reselect_topic(&mut topic_list_state, &visible_topics, &topic_list, &filter);
+                }
+                (_, KeyCode::Char(c)) if filtering => {
+                    filter.push(c);
+                    reselect_topic(&mut topic_list_state, &visible_topics, &topic_list, &filter);
+                }
+                (MenuItem::Topics, KeyCode::Enter) if focus == Focus::Messages => {
+                    if let Some(Line::Message(message)) =
+                        msgs_list_state.selected().and_then(|i| msgs.get(i))
+                    {
+                        dialog = Some(Dialog::MessageDetail(message.clone(), 0));
+                    }
+                }
+                // Keys free of commands start a jump, and once one is under
+                // way every key adds to it. Alt starts one with any letter.
+                (MenuItem::Topics, KeyCode::Char(c))
+                    if focus == Focus::Topics
+                        && !event.modifiers.contains(KeyModifiers::CONTROL)
+                        && (event.modifiers.contains(KeyModifiers::ALT)
+                            || !is_command_key(c)
+                            || (!type_ahead.is_empty()
+                                && type_ahead_at.elapsed() <= TYPE_AHEAD_IDLE)) =>
+                {
+                    if type_ahead_at.elapsed() > TYPE_AHEAD_IDLE {
+                        type_ahead.clear();
+                    }
+                    type_ahead.push(c);
+                    type_ahead_at = Instant::now();
+                    // Nothing starting with the letters keeps the selection.
+                    if let Some(index) = find_prefix(&visible_topics, &type_ahead) {
+                        detail_state.select(None);
+                        topic_list_state.select(Some(index));
+                    }
+                }
+                (MenuItem::Produce, KeyCode::Esc) => active_menu_item = MenuItem::Topics,
+                (MenuItem::Produce, KeyCode::Enter) => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        let selected = selected.name;
+                        // The key is kept so several messages can share it.
+                        let key = produce_form.fields[0].value.clone();
+                        let msg = std::mem::take(&mut produce_form.fields[1].value);
+                        let result = client.create_producer().and_then(|mut producer| {
+                            client.produce_keyed(&mut producer, &selected, &key, &msg)
+                        });
+                        let text = match result {
+                            Ok(delivery) => {
+                                let at = delivery.map_or(String::new(), |d| {
+                                    format!(" partition {} @ offset {}", d.partition, d.offset)
+                                });
+                                if key.is_empty() {
+                                    format!("Sent to {}{}: {}", selected, at, msg)
+                                } else {
+                                    format!("Sent to {}{}: {} => {}", selected, at, key, msg)
+                                }
+                            }
+                            Err(e) => format!("Failed to send to {}: {}", selected, e),
+                        };
+                        status = Some((text, Instant::now()));
+                    }
+                }
+                (MenuItem::Produce, KeyCode::Tab) => produce_form.cycle(),
+                (MenuItem::Produce, KeyCode::Backspace) => produce_form.backspace(),
+                (MenuItem::Produce, KeyCode::Char(c)) => produce_form.input(c),
+                (_, KeyCode::Char('q')) => {
+                    // Commits before leaving so the group resumes after the
+                    // last pulled message next time.
+                    match session.take().map(PullSession::close) {
+                        Some(Err(e)) => dialog = Some(Dialog::ConfirmQuit(e.to_string())),
+                        _ => quit = true,
+                    }
+                }
+                (_, KeyCode::Char('t')) => active_menu_item = MenuItem::Topics,
+                (_, KeyCode::Char('?')) => dialog = Some(Dialog::Help(0)),
+                (_, KeyCode::Char('P')) => match load_profiles(config_file) {
+                    Ok(configs) => {
+                        let names: Vec<String> =
+                            configs.iter().map(|config| config.name().clone()).collect();
+                        let current = names
+                            .iter()
+                            .position(|name| name == kafka_config.name())
+                            .unwrap_or(0);
+                        dialog = Some(Dialog::SwitchProfile(names, current));
+                    }
+                    Err(e) => status = Some((e.to_string(), Instant::now())),
+                },
+                (_, KeyCode::Char('l')) => {
+                    dialog = Some(Dialog::Leaders(leader_counts(&topic_list)))
+                }
+                (_, KeyCode::Char('/')) => filtering = true,
+                (_, KeyCode::Char('f')) => {
+                    searching = true;
+                    search.clear();
+                    focus = Focus::Messages;
+                }
+                (_, KeyCode::Char('n')) if focus == Focus::Messages && !search.is_empty() => {
+                    jump_to_match(&mut msgs_list_state, &msgs, &search, true)
+                }
+                (_, KeyCode::Char('N')) if focus == Focus::Messages && !search.is_empty() => {
+                    jump_to_match(&mut msgs_list_state, &msgs, &search, false)
+                }
+                (MenuItem::Consumers, KeyCode::Char('w'))
+                | (MenuItem::Topics, KeyCode::Char('d'))
+                | (_, KeyCode::Char('n') | KeyCode::Char('s'))
+                    if read_only =>
+                {
+                    status = Some((
+                        "Not available in read-only mode".to_string(),
+                        Instant::now(),
+                    ));
+                }
+                (_, KeyCode::Char('n')) if connection_error.is_none() => {
+                    let form = Form::new(
+                        "Create topic",
+                        &["Name", "Partitions", "Replication factor"],
+                    )
+                    .with_value(1, "1")
+                    .with_value(2, "1");
+                    dialog = Some(Dialog::CreateTopic(form));
+                }
+                (_, KeyCode::Char('i')) if connection_error.is_none() => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        let configs = client
+                            .describe_topic_configs(&selected.name)
+                            .map_err(|e| e.to_string());
+                        if !compact && configs.as_deref().is_ok_and(is_compacted) {
+                            status = Some((compact_hint(&selected.name), Instant::now()));
+                        }
+                        dialog = Some(Dialog::TopicConfigs(selected.name, configs, 0));
+                    }
+                }
+                (_, KeyCode::Char('k')) => {
+                    let form =
+                        Form::new("Key filter", &["Key prefix or /regex (empty to show all)"])
+                            .with_value(0, key_filter.text());
+                    dialog = Some(Dialog::KeyFilter(form));
+                }
+                (_, KeyCode::Char('a')) if connection_error.is_none() => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        let partition = pull_partition.unwrap_or(0).to_string();
+                        let form = Form::new(
+                            &format!("Read {} from offset", selected.name),
+                            &["Partition", "Offset"],
+                        )
+                        .with_value(0, &partition);
+                        dialog = Some(Dialog::ReadFromOffset(selected.name, form));
+                    }
+                }
+                (_, KeyCode::Char('b')) if connection_error.is_none() => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        let form = Form::new(
+                            &format!("Read {} since", selected.name),
+                            &["Time (HH:MM, YYYY-MM-DD HH:MM or RFC 3339)"],
+                        );
+                        dialog = Some(Dialog::ReadFromTime(selected.name, form));
+                    }
+                }
+                (_, KeyCode::Char('v')) if connection_error.is_none() => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        let form = Form::new(
+                            &format!("Read the last messages of {}", selected.name),
+                            &["Messages"],
+                        )
+                        .with_value(0, &DEFAULT_TAIL.to_string());
+                        dialog = Some(Dialog::ReadLast(selected.name, form));
+                    }
+                }
+                (MenuItem::Topics, KeyCode::Char('d')) if connection_error.is_none() => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        dialog = Some(
+                            if selected.message_count() > kafka_config.delete_confirm_messages() {
+                                let form = Form::new(
+                                    &format!(
+                                        "Delete {} ({} messages)",
+                                        selected.name,
+                                        selected.message_count()
+                                    ),
+                                    &["Type the topic name to confirm"],
+                                );
+                                Dialog::TypedDelete(selected.name, form)
+                            } else {
+                                Dialog::ConfirmDelete(selected.name)
+                            },
+                        );
+                    }
+                }
+                (MenuItem::Topics, KeyCode::Esc) => {
+                    filter.clear();
+                    reselect_topic(&mut topic_list_state, &visible_topics, &topic_list, &filter);
+                }
+                (_, KeyCode::Char('s')) => active_menu_item = MenuItem::Produce,
+                (_, KeyCode::Char('g')) if connection_error.is_none() => {
+                    active_menu_item = MenuItem::Consumers;
+                    groups.load(&client);
+                }
+                (MenuItem::Consumers, KeyCode::Esc) => active_menu_item = MenuItem::Topics,
+                (MenuItem::Consumers, KeyCode::Char('w')) => {
+                    if let Some(group) = groups.selected().cloned() {
+                        let topic = groups
+                            .offsets
+                            .first()
+                            .map(|o| o.topic.clone())
+                            .or_else(|| {
+                                get_selected_topic(&topic_list_state, &visible_topics)
+                                    .map(|t| t.name)
+                            })
+                            .unwrap_or_default();
+                        let form = Form::new(
+                            &format!("Reset offsets of {}", group),
+                            &[
+                                "Topic",
+                                "Target (earliest, latest or an offset)",
+                                "Type the group name to confirm",
+                            ],
+                        )
+                        .with_value(0, &topic)
+                        .with_value(1, "earliest");
+                        dialog = Some(Dialog::ResetOffsets(group, form));
+                    }
+                }
+                (MenuItem::Consumers, KeyCode::Down) => groups.select(&client, 1),
+                (MenuItem::Consumers, KeyCode::Up) => groups.select(&client, -1),
+                (_, KeyCode::Char('r')) => refreshing = true,
+                (_, KeyCode::Char('m')) if connection_error.is_none() => measuring = true,
+                (_, KeyCode::Char('o')) => {
+                    fetch_offset = match fetch_offset {
+                        FetchOffset::Earliest => FetchOffset::Latest,
+                        _ => FetchOffset::Earliest,
+                    }
+                }
+                (_, KeyCode::Char('j')) => pretty_json = !pretty_json,
+                (_, KeyCode::Char('h')) => hex = !hex,
+                (_, KeyCode::Char('x')) => exact_offsets = !exact_offsets,
+                (_, KeyCode::Char('z')) => {
+                    compact = !compact;
+                    if !compact {
+                        status = Some((
+                            "Showing every message again from the next pull".to_string(),
+                            Instant::now(),
+                        ));
+                    }
+                }
+                (_, KeyCode::Char('e')) => {
+                    let topic = get_selected_topic(&topic_list_state, &visible_topics)
+                        .map(|t| t.name)
+                        .unwrap_or_else(|| "messages".to_string());
+                    let notice = match export(&topic, &msgs) {
+                        Ok(path) => format!("Exported messages to {}", path.display()),
+                        Err(e) => format!("Could not export messages: {}", e),
+                    };
+                    status = Some((notice, Instant::now()));
+                }
+                (_, KeyCode::Char('y')) => {
+                    if let Some(line) = msgs_list_state.selected().and_then(|i| msgs.get(i)) {
+                        let text = match line {
+                            Line::Message(message) if pretty_json => message
+                                .pretty_value()
+                                .unwrap_or_else(|| message.value.clone()),
+                            Line::Message(message) => message.value.clone(),
+                            Line::Notice(notice) => notice.clone(),
+                        };
+                        let notice = match clipboard::copy(&text) {
+                            Ok(()) => "Copied message to the clipboard".to_string(),
+                            Err(e) => format!("Could not copy message: {}", e),
+                        };
+                        status = Some((notice, Instant::now()));
+                    }
+                }
+                (_, KeyCode::Char('B')) => {
+                    let brokers = summary.brokers.join(",");
+                    let notice = match clipboard::copy(&brokers) {
+                        Ok(()) => format!("Copied {} to the clipboard", brokers),
+                        Err(e) => format!("Could not copy the brokers: {}", e),
+                    };
+                    status = Some((notice, Instant::now()));
+                }
+                (_, KeyCode::Char('C')) => {
+                    commit = !commit;
+                    // The open session keeps the mode it was opened with.
+                    if let Some(previous) = session.take() {
+                        let topic = previous.topic.clone();
+                        if let Err(e) = previous.close() {
+                            msgs.push_back(Line::Notice(format!(
+                                "Could not commit the offsets pulled from {}: {}",
+                                topic, e
+                            )));
+                        }
+                    }
+                    live = None;
+                    let notice = if commit {
+                        "Committing pulled offsets"
+                    } else {
+                        "Not committing pulled offsets; the group's offsets stay where they are"
+                    };
+                    status = Some((notice.to_string(), Instant::now()));
+                }
+                (_, KeyCode::Char('c')) => {
+                    if !msgs.is_empty() {
+                        status = Some((
+                            format!("Cleared {} lines, u to undo", msgs.len()),
+                            Instant::now(),
+                        ));
+                        last_cleared =
+                            Some((std::mem::take(&mut msgs), msgs_topic.take(), trimmed));
+                    }
+                    msgs_topic = None;
+                    trimmed = false;
+                    msgs_list_state.select(None);
+                }
+                (_, KeyCode::Char('u')) => match last_cleared.take() {
+                    Some((mut cleared, topic, was_trimmed)) => {
+                        status =
+                            Some((format!("Restored {} lines", cleared.len()), Instant::now()));
+                        // Notices added since the Clear stay after the restored lines.
+                        cleared.append(&mut msgs);
+                        msgs = cleared;
+                        msgs_topic = topic;
+                        trimmed = was_trimmed;
+                    }
+                    None => status = Some(("Nothing to undo".to_string(), Instant::now())),
                 },
-                KeyCode::Char('p') => {
-                    let selected = get_selected_topic(&topic_list_state.clone(), topic_list.clone()).name;
-                    let mut consumer = client.create_consumer(&selected);
-                    for ms in consumer.poll().unwrap().iter() {
-                        for m in ms.messages() {
-                            let message = parse_message(m.value);
-                            msgs.push(message)
+                (_, KeyCode::Char('p')) if live.is_some() => live = None,
+                (_, KeyCode::Char('p')) => {
+                    if let Some(selected) = get_selected_topic(&topic_list_state, &visible_topics) {
+                        last_cleared = None;
+                        let mut open = match session.take() {
+                            Some(open)
+                                if open.reads(&selected.name, pull_partition, fetch_offset) =>
+                            {
+                                open
+                            }
+                            previous => {
+                                if !compact
+                                    && client
+                                        .describe_topic_configs(&selected.name)
+                                        .is_ok_and(|configs| is_compacted(&configs))
+                                {
+                                    status = Some((compact_hint(&selected.name), Instant::now()));
+                                }
+                                // Closed rather than dropped so that failing to
+                                // commit what it pulled doesn't go unnoticed.
+                                if let Some(previous) = previous {
+                                    let topic = previous.topic.clone();
+                                    if let Err(e) = previous.close() {
+                                        msgs.push_back(Line::Notice(format!(
+                                            "Could not commit the offsets pulled from {}: {}",
+                                            topic, e
+                                        )));
+                                    }
+                                }
+                                PullSession::open(
+                                    &client,
+                                    &selected.name,
+                                    pull_partition,
+                                    &kafka_config.group(),
+                                    fetch_offset,
+                                    commit,
+                                )
+                            }
+                        };
+                        // Latest has nothing to catch up on, so keep following the
+                        // topic on every tick until p is pressed again. Without a
+                        // group there are no committed offsets to restart from, so
+                        // take everything each poll returns.
+                        if matches!(fetch_offset, FetchOffset::Latest) {
+                            live = Some(0);
+                            open.pull(usize::MAX);
+                        } else if !commit {
+                            // Nothing was committed to restart from either.
+                            open.pull(usize::MAX);
+                        } else {
+                            open.pull(kafka_config.max_messages());
                         }
-                        consumer.consume_messageset(ms).unwrap();
+                        session = Some(open);
                     }
-                    consumer.commit_consumed().unwrap();
                 }
-                KeyCode::Down => match active_menu_item {
-                    MenuItem::Topics => {
+                (_, KeyCode::Tab) => focus = focus.next(),
+                (_, KeyCode::Down) => match focus {
+                    Focus::Topics => {
+                        detail_state.select(None);
+                        let amount_topics = visible_topics.len();
+                        clamp_selection(&mut topic_list_state, amount_topics);
                         if let Some(selected) = topic_list_state.selected() {
-                            let amount_topics = topic_list.len();
                             if selected >= amount_topics - 1 {
                                 topic_list_state.select(Some(0));
                             } else {
@@ -235,12 +1610,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     }
-                    _ => {}
+                    Focus::Messages => scroll(&mut msgs_list_state, msgs.len(), 1),
+                    Focus::Detail => {
+                        let partitions = get_selected_topic(&topic_list_state, &visible_topics)
+                            .map_or(0, |topic| topic.partitions.len());
+                        select_partition(&mut detail_state, partitions, 1)
+                    }
                 },
-                KeyCode::Up => match active_menu_item {
-                    MenuItem::Topics => {
+                (_, KeyCode::Up) => match focus {
+                    Focus::Topics => {
+                        detail_state.select(None);
+                        let amount_topics = visible_topics.len();
+                        clamp_selection(&mut topic_list_state, amount_topics);
                         if let Some(selected) = topic_list_state.selected() {
-                            let amount_topics = topic_list.len();
                             if selected > 0 {
                                 topic_list_state.select(Some(selected - 1));
                             } else {
@@ -248,135 +1630,1685 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     }
-                    _ => {}
+                    Focus::Messages => scroll(&mut msgs_list_state, msgs.len(), -1),
+                    Focus::Detail => {
+                        let partitions = get_selected_topic(&topic_list_state, &visible_topics)
+                            .map_or(0, |topic| topic.partitions.len());
+                        select_partition(&mut detail_state, partitions, -1)
+                    }
                 },
+                (_, KeyCode::PageDown | KeyCode::PageUp) if focus == Focus::Detail => {
+                    let partitions = get_selected_topic(&topic_list_state, &visible_topics)
+                        .map_or(0, |topic| topic.partitions.len());
+                    let page = if event.code == KeyCode::PageDown {
+                        MESSAGES_PAGE
+                    } else {
+                        -MESSAGES_PAGE
+                    };
+                    select_partition(&mut detail_state, partitions, page)
+                }
+                (_, KeyCode::PageDown) => scroll(&mut msgs_list_state, msgs.len(), MESSAGES_PAGE),
+                (_, KeyCode::PageUp) => scroll(&mut msgs_list_state, msgs.len(), -MESSAGES_PAGE),
                 _ => {}
             },
-            Event::Tick => {}
+            Event::Tick => {
+                for (host, healthy) in health_checks.iter().flat_map(|checks| checks.try_iter()) {
+                    match broker_health
+                        .iter_mut()
+                        .find(|(checked, _)| *checked == host)
+                    {
+                        Some(entry) => entry.1 = healthy,
+                        None => broker_health.push((host, healthy)),
+                    }
+                }
+                if status
+                    .as_ref()
+                    .is_some_and(|(_, at)| at.elapsed() >= STATUS_TTL)
+                {
+                    status = None;
+                }
+                if let (Some(_), Some(open)) = (live, session.as_mut()) {
+                    let following = get_selected_topic(&topic_list_state, &visible_topics)
+                        .is_some_and(|t| open.reads(&t.name, pull_partition, fetch_offset));
+                    if following {
+                        open.pull(usize::MAX);
+                    } else {
+                        // The selected topic, partition or offset changed.
+                        live = None;
+                    }
+                }
+            }
+        }
+
+        if quit {
+            disable_raw_mode()?;
+            terminal.show_cursor()?;
+            break;
         }
     }
 
+    let state = config::State {
+        profile: Some(kafka_config.name().clone()),
+        topic: get_selected_topic(&topic_list_state, &filter_topics(&topic_list, &filter))
+            .map(|t| t.name),
+    };
+    if let Err(e) = config::save_state(&state) {
+        eprintln!("{}", e);
+    }
     Ok(())
 }
 
-fn messages_block<'a>(msgs: Vec<String>) -> List<'a> {
-    let heading = Block::default()
-        .borders(Borders::ALL)
-        .style(Style::default().fg(Color::White))
-        .title("Messages")
-        .border_type(BorderType::Plain);
-
-    let items: Vec<_> = msgs
-        .iter()
-        .map(|msg| {
-            ListItem::new(Spans::from(vec![Span::styled(
-                msg.clone(),
-                Style::default(),
-            )]))
-        })
-        .collect();
-    return List::new(items).block(heading).highlight_style(
-        Style::default()
-            .bg(Color::Yellow)
-            .fg(Color::Black)
-            .add_modifier(Modifier::BOLD),
-    );
+/// Leaves raw mode and shows the cursor before the panic message is printed,
+/// so a panic in the event loop doesn't leave the terminal unusable.
+fn restore_terminal_on_panic() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), cursor::Show);
+        default_hook(info);
+    }));
 }
 
-fn broker_info_label(brokers: Vec<String>) -> String {
-    return format!("{} {}", "Brokers:", brokers.join(", "));
+/// Moves the selection of a list with `len` items by `delta`, stopping at
+/// either end rather than wrapping around.
+fn scroll(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        state.select(None);
+        return;
+    }
+    let current = state.selected().map(|i| i as isize).unwrap_or(-1);
+    let target = (current + delta).clamp(0, len as isize - 1);
+    state.select(Some(target as usize));
 }
 
-fn num_topics_label(num: usize) -> String {
-    return format!("{} {}", "Number of Topics:", num.to_string());
+/// Moves the partition selected in the Detail table by `delta`. Moving up
+/// past the first partition clears the selection, pulling all partitions.
+fn select_partition(state: &mut TableState, len: usize, delta: isize) {
+    let current = state.selected().map(|i| i as isize).unwrap_or(-1);
+    let target = (current + delta).clamp(-1, len as isize - 1);
+    state.select(if target < 0 {
+        None
+    } else {
+        Some(target as usize)
+    });
 }
 
-fn get_selected_topic(topic_list_state: &ListState, topic_list: Vec<TopicData>) -> TopicData {
-    return topic_list
-        .get(
-            topic_list_state
-                .selected()
-                .expect("there is always a selected topic"),
-        )
-        .expect("exists")
-        .clone();
+fn usage_error(error: UsageError, config_file: Option<&Path>) -> ! {
+    eprintln!("{}\n\n{}", error, cli::USAGE);
+    if let Ok(configs) = load_profiles(config_file) {
+        let names: Vec<&str> = configs.iter().map(|c| c.name().as_str()).collect();
+        eprintln!("\nAvailable profiles: {}", names.join(", "));
+    }
+    process::exit(2);
 }
 
-fn render_topics<'a>(
-    topic_list_state: &ListState,
-    topic_list: Vec<TopicData>,
-) -> (List<'a>, Table<'a>) {
-    let topics = Block::default()
-        .borders(Borders::ALL)
-        .style(Style::default().fg(Color::White))
-        .title("Topics")
-        .border_type(BorderType::Plain);
+/// Every profile in `config_file`, or in the default config file.
+fn load_profiles(config_file: Option<&Path>) -> Result<Vec<KafkaConfig>, config::ConfigError> {
+    match config_file {
+        Some(path) => config::load_all_from(path),
+        None => config::load_all(),
+    }
+}
 
-    let items: Vec<_> = topic_list
-        .iter()
-        .map(|topic| {
-            ListItem::new(Spans::from(vec![Span::styled(
-                topic.name.clone(),
-                Style::default(),
-            )]))
+/// The profile marked `"default": true`, if any. A config that can't be
+/// loaded has none; opening the profile reports what's wrong with it.
+fn default_profile(config_file: Option<&Path>) -> Option<String> {
+    load_profiles(config_file)
+        .ok()?
+        .into_iter()
+        .find(KafkaConfig::is_default)
+        .map(|config| config.name().clone())
+}
+
+/// Loads `profile` from `config_file`, or the default config file, exiting
+/// with an error message if it can't be used.
+fn load_profile(config_file: Option<&Path>, profile: String) -> KafkaConfig {
+    match try_load_profile(config_file, profile) {
+        Ok(kafka_config) => kafka_config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Loads `profile` like `load_profile`, but reports why it can't be used
+/// instead of exiting, for switching profiles in the TUI.
+fn try_load_profile(config_file: Option<&Path>, profile: String) -> Result<KafkaConfig, String> {
+    let loaded = match config_file {
+        Some(path) => config::get_from(path, profile.clone()),
+        None => config::get(profile.clone()),
+    };
+    let kafka_config = match loaded {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            let names = match config_file {
+                Some(path) => config::list_profile_names_from(path),
+                None => config::list_profile_names(),
+            };
+            return Err(match names {
+                Ok(names) if !names.is_empty() => format!(
+                    "No profile named {} in the config file, available profiles: {}",
+                    profile,
+                    names.join(", ")
+                ),
+                _ => format!("No profile named {} in the config file", profile),
+            });
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    if kafka_config.uses_sasl() {
+        return Err(format!(
+            "Profile {} sets SASL credentials, but SASL authentication ({}) is not supported \
+             by the kafka client library yet",
+            kafka_config.name(),
+            kafka_config.sasl_mechanism().name()
+        ));
+    }
+    Ok(kafka_config)
+}
+
+fn connect(kafka_config: &KafkaConfig) -> SimpleKafkaClient {
+    match new_client(kafka_config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn new_client(kafka_config: &KafkaConfig) -> Result<SimpleKafkaClient, KafkaClientError> {
+    SimpleKafkaClient::new(
+        kafka_config.broker().clone(),
+        kafka_config.tls().as_ref(),
+        kafka_config.connect_timeout(),
+        kafka_config.request_retries(),
+        kafka_config.consumer(),
+        kafka_config.producer(),
+        Decoder::new(kafka_config.schema_registry(), kafka_config.proto()),
+    )
+}
+
+/// Prints what went into this build, for bug reports. Works without a
+/// config, so it never connects to anything.
+fn version() {
+    print!("kafku {}", env!("CARGO_PKG_VERSION"));
+    match option_env!("KAFKU_GIT_HASH") {
+        Some(hash) => println!(" ({})", hash),
+        None => println!(),
+    }
+    println!(
+        "kafka crate {}",
+        option_env!("KAFKU_KAFKA_VERSION").unwrap_or("unknown")
+    );
+}
+
+/// Prints the topics of a profile for scripts and shell completion, either
+/// as bare names or, with `json`, with their partitions and offsets.
+fn topics(kafka_config: KafkaConfig, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&kafka_config);
+    let refreshed = client.refresh_with_retry(
+        &kafka_config.group(),
+        kafka_config.metadata_attempts(),
+        |retry| {
+            eprintln!(
+                "Could not list topics, retrying (attempt {}/{})",
+                retry.attempt(),
+                retry.attempts()
+            )
+        },
+    );
+    if let Err(e) = refreshed {
+        eprintln!("Could not list topics: {}", e);
+        process::exit(1);
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let written = if json {
+        serde_json::to_writer_pretty(&mut out, &client.list_topic_details())
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(out))
+    } else {
+        client
+            .list_topics()
+            .iter()
+            .try_for_each(|topic| writeln!(out, "{}", topic))
+    };
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// How far a consumer group is behind on one partition.
+#[derive(Serialize)]
+struct PartitionLag {
+    topic: String,
+    partition: i32,
+    committed: i64,
+    latest: i64,
+    lag: i64,
+}
+
+/// Prints `group`'s lag on every partition it committed offsets for, and
+/// exits with status 1 when the total exceeds `threshold`, for health checks.
+fn lag(
+    kafka_config: KafkaConfig,
+    group: &str,
+    json: bool,
+    threshold: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&kafka_config);
+    let refreshed = client.refresh_with_retry(group, kafka_config.metadata_attempts(), |retry| {
+        eprintln!(
+            "Could not load offsets, retrying (attempt {}/{})",
+            retry.attempt(),
+            retry.attempts()
+        )
+    });
+    if let Err(e) = refreshed {
+        eprintln!("Could not load offsets: {}", e);
+        process::exit(1);
+    }
+    let lags: Vec<PartitionLag> = client
+        .list_topic_details()
+        .into_iter()
+        .flat_map(|topic| {
+            topic.partitions.into_iter().filter_map(move |p| {
+                Some(PartitionLag {
+                    topic: topic.name.clone(),
+                    partition: p.id,
+                    committed: p.committed_offset?,
+                    latest: p.offset,
+                    lag: p.lag()?,
+                })
+            })
         })
         .collect();
+    let total: i64 = lags.iter().map(|lag| lag.lag).sum();
+    if lags.is_empty() {
+        eprintln!("{} has no committed offsets", group);
+    }
 
-    let selected_topic = get_selected_topic(topic_list_state, topic_list);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let written = if json {
+        serde_json::to_writer_pretty(
+            &mut out,
+            &serde_json::json!({ "group": group, "total": total, "partitions": lags }),
+        )
+        .map_err(io::Error::from)
+        .and_then(|_| writeln!(out))
+    } else {
+        lags.iter().try_for_each(|lag| {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                lag.topic, lag.partition, lag.committed, lag.latest, lag.lag
+            )
+        })
+    };
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    if let Some(threshold) = threshold.filter(|threshold| total > *threshold) {
+        eprintln!(
+            "Total lag of {} is {}, over the threshold of {}",
+            group, total, threshold
+        );
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the values of messages on `topic` to stdout, one per line, without
+/// starting the TUI. Runs until `max` messages were printed or it's killed.
+fn consume(
+    kafka_config: KafkaConfig,
+    topic: &str,
+    max: Option<usize>,
+    from: FetchOffset,
+    key_filter: Option<&str>,
+    commit: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key_filter = match KeyFilter::new(key_filter.unwrap_or_default()) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Invalid --key-filter regex: {}", e);
+            process::exit(2);
+        }
+    };
+    let client = connect(&kafka_config);
+    if max == Some(0) {
+        return Ok(());
+    }
+    let consumer = match client.create_consumer(topic, &kafka_config.group(), from) {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            eprintln!("Could not consume from {}: {}", topic, e);
+            process::exit(1);
+        }
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut printed = 0;
+    let mut write_error = None;
+    let result = client.start_consumer(consumer, commit, &mut |message| {
+        // Skipped messages are still committed, like printed ones.
+        if !message.key_matches(&key_filter) {
+            return true;
+        }
+        if let Err(e) = writeln!(out, "{}", message.value).and_then(|_| out.flush()) {
+            write_error = Some(e);
+            return false;
+        }
+        printed += 1;
+        max.is_none_or(|max| printed < max)
+    });
+    match (write_error, result) {
+        // The reader went away, e.g. `kafku consume ... | head`.
+        (Some(e), _) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        (Some(e), _) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        (None, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        (None, Ok(())) => Ok(()),
+    }
+}
+
+/// Sends each line of stdin to `topic` as a message. With `key_separator`,
+/// lines containing it are split into a key and a value at its first
+/// occurrence.
+fn produce(
+    kafka_config: KafkaConfig,
+    topic: &str,
+    key_separator: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&kafka_config);
+    let mut producer = open_producer(&kafka_config, &client, topic);
+    let mut sent = 0;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let result = match key_separator.and_then(|separator| line.split_once(separator)) {
+            Some((key, value)) => client.produce_keyed(&mut producer, topic, key, value),
+            None => client.produce(&mut producer, topic.to_string(), line),
+        };
+        if let Err(e) = result {
+            eprintln!("Sent {} messages to {} before failing: {}", sent, topic, e);
+            process::exit(1);
+        }
+        sent += 1;
+    }
+    eprintln!("Sent {} messages to {}", sent, topic);
+    Ok(())
+}
+
+/// How long the message rate of a topic is measured over.
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often `replay` reports how many messages it sent so far.
+const REPLAY_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
-    let list = List::new(items).block(topics).highlight_style(
-        Style::default()
-            .bg(Color::Yellow)
-            .fg(Color::Black)
-            .add_modifier(Modifier::BOLD),
+/// How many records `replay` sends per request.
+const REPLAY_BATCH_SIZE: u32 = 500;
+
+/// A line of a replay file. Exported messages have this shape too, along
+/// with fields replaying ignores.
+#[derive(Deserialize)]
+struct ReplayRecord {
+    key: Option<String>,
+    value: String,
+    /// The value's original bytes, in base64. Exports carry them, since
+    /// `value` is only how the bytes were shown.
+    #[serde(default, deserialize_with = "from_base64")]
+    raw: Option<Vec<u8>>,
+}
+
+/// Reads `raw` from base64, failing the line when it's malformed.
+fn from_base64<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    base64::decode(&text)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom("raw is not valid base64"))
+}
+
+/// Sends the records in the JSONL `file` to `topic` in order, at most `rate`
+/// per second when given, to reproduce captured traffic. Records go out in
+/// batches, no larger than a second's worth when rate limited.
+fn replay(
+    kafka_config: KafkaConfig,
+    topic: &str,
+    file: &Path,
+    rate: Option<u32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lines = match fs::File::open(file) {
+        Ok(opened) => io::BufReader::new(opened).lines(),
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file.display(), e);
+            process::exit(1);
+        }
+    };
+    let client = connect(&kafka_config);
+    let mut producer = open_producer(&kafka_config, &client, topic);
+    let started = Instant::now();
+    let mut reported = started;
+    let mut sent: u32 = 0;
+    let batch_size = rate.map_or(REPLAY_BATCH_SIZE, |rate| rate.min(REPLAY_BATCH_SIZE));
+    let mut batch = vec![];
+    // A final None flushes the last, partly filled batch.
+    for next in lines.enumerate().map(Some).chain(iter::once(None)) {
+        if let Some((number, line)) = next {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: ReplayRecord = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(e) => {
+                    eprintln!(
+                        "Sent {} messages to {} before line {} of {}: {}",
+                        sent,
+                        topic,
+                        number + 1,
+                        file.display(),
+                        e
+                    );
+                    process::exit(1);
+                }
+            };
+            batch.push((
+                record.key.map(String::into_bytes),
+                record.raw.unwrap_or_else(|| record.value.into_bytes()),
+            ));
+            if batch.len() < batch_size as usize {
+                continue;
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        if let Some(rate) = rate {
+            // Paced from the start rather than per batch, so slow sends
+            // don't lower the rate further.
+            let due = started + Duration::from_secs(1) * sent / rate;
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        match client.produce_batch(&mut producer, topic, std::mem::take(&mut batch)) {
+            Ok(count) => sent += count as u32,
+            Err(e) => {
+                eprintln!("Sent {} messages to {} before failing: {}", sent, topic, e);
+                process::exit(1);
+            }
+        }
+        if reported.elapsed() >= REPLAY_PROGRESS_INTERVAL {
+            eprintln!("Sent {} messages to {}", sent, topic);
+            reported = Instant::now();
+        }
+    }
+    eprintln!(
+        "Replayed {} messages to {} in {:.1}s",
+        sent,
+        topic,
+        started.elapsed().as_secs_f64()
     );
+    Ok(())
+}
 
-    let rows: Vec<Row> = selected_topic
-        .partitions
+/// A producer for `topic`, exiting with an error message if the profile is
+/// read-only or the producer can't be created.
+fn open_producer(kafka_config: &KafkaConfig, client: &SimpleKafkaClient, topic: &str) -> Producer {
+    if kafka_config.read_only() {
+        eprintln!(
+            "Could not produce to {}: profile {} is read-only",
+            topic,
+            kafka_config.name()
+        );
+        process::exit(1);
+    }
+    match client.create_producer() {
+        Ok(producer) => producer,
+        Err(e) => {
+            eprintln!("Could not produce to {}: {}", topic, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn init(config_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = match config_file {
+        Some(path) => path.to_path_buf(),
+        None => config::config_path()?,
+    };
+    if path.exists() {
+        print!("{} already exists, overwrite it? [y/N] ", path.display());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Leaving existing config untouched");
+            return Ok(());
+        }
+    }
+    config::write_template(&path)?;
+    println!("Wrote example config to {}", path.display());
+    println!("Each entry needs a unique \"name\" and a \"broker\" (or a list of \"brokers\").");
+    println!("Set \"group\" to choose the consumer group Pull commits under (defaults to your username).");
+    Ok(())
+}
+
+/// Drops the oldest lines so at most `max` remain, keeping the selection on
+/// the same line. Returns how many were dropped.
+fn cap_messages(msgs: &mut VecDeque<Line>, state: &mut ListState, max: usize) -> usize {
+    let excess = msgs.len().saturating_sub(max);
+    if excess > 0 {
+        msgs.drain(..excess);
+        state.select(state.selected().and_then(|i| i.checked_sub(excess)));
+    }
+    excess
+}
+
+/// Writes the consumed messages in `msgs` to a new file in `~/.kafka`, one
+/// JSON object per line, and returns its path.
+fn export(topic: &str, msgs: &VecDeque<Line>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+    let path = config::kafka_dir()?.join(format!("export-{}-{}.jsonl", topic, timestamp));
+    let mut out = io::BufWriter::new(fs::File::create(&path)?);
+    for msg in msgs {
+        if let Line::Message(message) = msg {
+            serde_json::to_writer(&mut out, message)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()?;
+    Ok(path)
+}
+
+/// Reads the create-topic form and asks the cluster to create the topic,
+/// returning its name.
+fn create_topic(client: &SimpleKafkaClient, form: &Form) -> Result<String, KafkaClientError> {
+    let name = form.value(0).to_string();
+    let partitions = form
+        .value(1)
+        .parse()
+        .map_err(|_| KafkaClientError::Invalid("partitions must be a number".to_string()))?;
+    let replication_factor = form.value(2).parse().map_err(|_| {
+        KafkaClientError::Invalid("replication factor must be a number".to_string())
+    })?;
+    client.create_topic(&name, partitions, replication_factor)?;
+    Ok(name)
+}
+
+/// Moves `group` on the topic entered in `form` to the target entered in it.
+fn reset_offsets(
+    client: &SimpleKafkaClient,
+    group: &str,
+    form: &Form,
+) -> Result<Vec<OffsetReset>, KafkaClientError> {
+    let target = match form.value(1) {
+        "earliest" => ResetTarget::Earliest,
+        "latest" => ResetTarget::Latest,
+        offset => ResetTarget::Offset(offset.parse().map_err(|_| {
+            KafkaClientError::Invalid("target must be earliest, latest or an offset".to_string())
+        })?),
+    };
+    client.reset_group_offsets(group, form.value(0), target)
+}
+
+/// Reads the messages at the partition and offset entered in `form`.
+fn read_from_offset(
+    client: &SimpleKafkaClient,
+    topic: &str,
+    form: &Form,
+    limit: usize,
+) -> Result<Vec<ConsumedMessage>, KafkaClientError> {
+    let partition = form
+        .value(0)
+        .parse()
+        .map_err(|_| KafkaClientError::Invalid("partition must be a number".to_string()))?;
+    let offset = form
+        .value(1)
+        .parse()
+        .map_err(|_| KafkaClientError::Invalid("offset must be a number".to_string()))?;
+    client.read_from_offset(topic, partition, offset, limit)
+}
+
+/// Reads every partition of `topic` from the time entered in `form`, up to
+/// `limit` messages in all.
+fn read_from_time(
+    client: &SimpleKafkaClient,
+    topic: &str,
+    form: &Form,
+    limit: usize,
+) -> Result<Vec<ConsumedMessage>, KafkaClientError> {
+    let since = parse_time(form.value(0)).ok_or_else(|| {
+        KafkaClientError::Invalid(format!("could not read \"{}\" as a time", form.value(0)))
+    })?;
+    let mut partitions = vec![];
+    for start in client.offsets_for_times(topic, since.timestamp_millis())? {
+        partitions.push(client.read_from_offset(topic, start.partition, start.offset, limit)?);
+    }
+    // Each partition keeps its oldest messages, those nearest the time.
+    let keep = fair_shares(partitions.iter().map(Vec::len).collect(), limit);
+    Ok(partitions
+        .into_iter()
+        .zip(keep)
+        .flat_map(|(messages, keep)| messages.into_iter().take(keep))
+        .collect())
+}
+
+/// The newest messages of `topic`, as many as the form asks for but at most
+/// `limit`.
+fn read_last(
+    client: &SimpleKafkaClient,
+    topic: &str,
+    form: &Form,
+    limit: usize,
+) -> Result<Vec<ConsumedMessage>, KafkaClientError> {
+    let n: usize = form
+        .value(0)
+        .parse()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| {
+            KafkaClientError::Invalid(format!(
+                "could not read \"{}\" as a number of messages",
+                form.value(0)
+            ))
+        })?;
+    client.read_tail(topic, n.min(limit))
+}
+
+/// A local time such as `14:00` (today), `2024-03-01 14:00` or
+/// `2024-03-01`, or an RFC 3339 timestamp.
+fn parse_time(input: &str) -> Option<DateTime<Local>> {
+    let input = input.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Some(time.with_timezone(&Local));
+    }
+    let today = Local::now().date_naive();
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
         .iter()
-        .map(|p| {
-            Row::new(vec![
-                Cell::from(Span::raw(p.id.to_string())),
-                Cell::from(Span::raw(p.leader.to_string())),
-                Cell::from(Span::raw(p.available.to_string())),
-                Cell::from(Span::raw(p.offset.to_string())),
-            ])
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .or_else(|| {
+            ["%H:%M:%S", "%H:%M"]
+                .iter()
+                .find_map(|format| NaiveTime::parse_from_str(input, format).ok())
+                .map(|time| today.and_time(time))
         })
-        .collect();
+        .or_else(|| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive).earliest()
+}
 
-    let topic_detail = Table::new(rows)
-        .header(Row::new(vec![
-            Cell::from(Span::styled(
-                "Partition Id",
-                Style::default().add_modifier(Modifier::BOLD),
-            )),
-            Cell::from(Span::styled(
-                "Leader",
-                Style::default().add_modifier(Modifier::BOLD),
-            )),
-            Cell::from(Span::styled(
-                "Available",
-                Style::default().add_modifier(Modifier::BOLD),
+/// A rectangle of the given percentage size centered inside `area`.
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
+}
+
+/// Draws `dialog` over the middle of `area`, hiding what's beneath it.
+fn render_dialog<B: Backend>(rect: &mut Frame<B>, dialog: &Dialog, area: Rect, accent: Color) {
+    let area = match dialog {
+        Dialog::MessageDetail(..) => centered_rect(90, 90, area),
+        _ => centered_rect(60, 50, area),
+    };
+    rect.render_widget(Clear, area);
+    rect.render_widget(dialog_block(dialog, accent), area);
+}
+
+fn dialog_block<'a>(dialog: &Dialog, accent: Color) -> Paragraph<'a> {
+    match dialog {
+        Dialog::CreateTopic(form)
+        | Dialog::TypedDelete(_, form)
+        | Dialog::ReadFromOffset(_, form)
+        | Dialog::ReadFromTime(_, form)
+        | Dialog::ReadLast(_, form)
+        | Dialog::ResetOffsets(_, form)
+        | Dialog::KeyFilter(form) => form_block(form, accent),
+        Dialog::ConfirmDelete(topic) => Paragraph::new(format!("Delete topic {}? (y/n)", topic))
+            .wrap(Wrap { trim: false })
+            .block(pane_block("Delete topic".to_string(), true, accent)),
+        Dialog::ConfirmQuit(error) => Paragraph::new(format!(
+            "Could not commit the offsets of pulled messages: {}\n\nQuit anyway? (y/n)",
+            error
+        ))
+        .wrap(Wrap { trim: false })
+        .block(pane_block("Quit".to_string(), true, accent)),
+        Dialog::Help(scroll) => Paragraph::new(help_lines(accent))
+            .scroll((*scroll, 0))
+            .block(pane_block(
+                "Keys (Up/Down to scroll, any key to close)".to_string(),
+                true,
+                accent,
             )),
-            Cell::from(Span::styled(
-                "Offset",
-                Style::default().add_modifier(Modifier::BOLD),
+        Dialog::SwitchProfile(names, selected) => {
+            Paragraph::new(profile_lines(names, *selected, accent)).block(pane_block(
+                "Switch profile (Up/Down to choose, Enter to switch)".to_string(),
+                true,
+                accent,
+            ))
+        }
+        Dialog::Leaders(counts) => Paragraph::new(leader_lines(counts, accent)).block(pane_block(
+            "Partition leaders (any key to close)".to_string(),
+            true,
+            accent,
+        )),
+        Dialog::MessageDetail(message, scroll) => Paragraph::new(detail_lines(message, accent))
+            .wrap(Wrap { trim: false })
+            .scroll((*scroll, 0))
+            .block(pane_block(
+                "Message (Up/Down to scroll, any key to close)".to_string(),
+                true,
+                accent,
             )),
-        ]))
+        Dialog::TopicConfigs(topic, configs, scroll) => {
+            Paragraph::new(config_lines(configs, accent))
+                .scroll((*scroll, 0))
+                .block(pane_block(
+                    format!("Configs of {} (Up/Down to scroll, any key to close)", topic),
+                    true,
+                    accent,
+                ))
+        }
+    }
+}
+
+/// One line per binding in `KEYMAP`, with the keys in a column of their own.
+fn help_lines<'a>(accent: Color) -> Vec<Spans<'a>> {
+    let width = KEYMAP
+        .iter()
+        .map(|binding| binding.key.len())
+        .max()
+        .unwrap_or(0);
+    KEYMAP
+        .iter()
+        .map(|binding| {
+            Spans::from(vec![
+                Span::styled(
+                    format!("{:<width$}  ", binding.key, width = width),
+                    Style::default().fg(accent),
+                ),
+                Span::raw(binding.action),
+            ])
+        })
+        .collect()
+}
+
+/// One line per config entry, with those set on the topic itself first and
+/// highlighted, and broker defaults dimmed after them.
+fn config_lines<'a>(configs: &Result<Vec<TopicConfig>, String>, accent: Color) -> Vec<Spans<'a>> {
+    let configs = match configs {
+        Ok(configs) => configs,
+        Err(e) => return vec![Spans::from(format!("Configs unavailable: {}", e))],
+    };
+    let (overridden, defaults): (Vec<_>, Vec<_>) =
+        configs.iter().partition(|config| !config.is_default);
+    overridden
+        .iter()
+        .map(|config| (config, Style::default().fg(accent)))
+        .chain(
+            defaults
+                .iter()
+                .map(|config| (config, Style::default().fg(Color::DarkGray))),
+        )
+        .map(|(config, style)| {
+            let value = config.value.as_deref().unwrap_or("<hidden>");
+            Spans::from(Span::styled(format!("{} = {}", config.name, value), style))
+        })
+        .collect()
+}
+
+/// Where `message` was read from and its key, then the whole value, pretty
+/// printed when it's JSON.
+fn detail_lines<'a>(message: &ConsumedMessage, accent: Color) -> Vec<Spans<'a>> {
+    let label = |name: &str| Span::styled(format!("{:<11}", name), Style::default().fg(accent));
+    let mut lines = vec![
+        Spans::from(vec![
+            label("Partition"),
+            Span::raw(message.partition.to_string()),
+        ]),
+        Spans::from(vec![label("Offset"), Span::raw(message.offset.to_string())]),
+        Spans::from(vec![
+            label("Key"),
+            Span::raw(message.key.clone().unwrap_or_else(|| "<null>".to_string())),
+        ]),
+        Spans::from(vec![
+            label("Size"),
+            Span::raw(format!("{} bytes", message.raw.len())),
+        ]),
+        Spans::from(""),
+    ];
+    let value = message
+        .pretty_value()
+        .unwrap_or_else(|| message.value.clone());
+    lines.extend(value.lines().map(|line| Spans::from(line.to_string())));
+    lines
+}
+
+fn form_block<'a>(form: &Form, accent: Color) -> Paragraph<'a> {
+    let mut text = form_lines(form, accent);
+    text.push(Spans::from(""));
+    text.push(Spans::from(
+        "Enter: next/submit  Tab: switch field  Esc: cancel",
+    ));
+    Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(pane_block(form.title.clone(), true, accent))
+}
+
+/// One line per field, highlighting the one being edited.
+fn form_lines<'a>(form: &Form, accent: Color) -> Vec<Spans<'a>> {
+    form.fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let style = if i == form.active {
+                Style::default().fg(accent)
+            } else {
+                Style::default()
+            };
+            let cursor = if i == form.active { "_" } else { "" };
+            Spans::from(Span::styled(
+                format!("{}: {}{}", field.label, field.value, cursor),
+                style,
+            ))
+        })
+        .collect()
+}
+
+fn connection_error_block<'a>(error: &str, brokers: &[String], accent: Color) -> Paragraph<'a> {
+    let text = vec![
+        Spans::from(Span::styled(
+            format!("Could not load metadata from {}", brokers.join(", ")),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(error.to_owned()),
+        Spans::from(""),
+        Spans::from(
+            "Check that the brokers are reachable, then press 'r' to retry or 'q' to quit.",
+        ),
+    ];
+    Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(pane_block("Connection error".to_string(), false, accent))
+}
+
+fn produce_block<'a>(form: &Form, topic: &str, accent: Color) -> Paragraph<'a> {
+    Paragraph::new(form_lines(form, accent))
+        .wrap(Wrap { trim: false })
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
-                .title("Detail")
+                .title(format!(
+                    "Produce to {} (Enter to send, Tab to switch key/value, Esc to leave)",
+                    topic
+                ))
                 .border_type(BorderType::Plain),
         )
+}
+
+/// The style of the selected line in lists and tables.
+fn selected_style(accent: Color) -> Style {
+    Style::default()
+        .bg(accent)
+        .fg(Color::Black)
+        .add_modifier(Modifier::BOLD)
+}
+
+/// A bordered pane whose border is highlighted while it has focus.
+fn pane_block<'a>(title: String, focused: bool, accent: Color) -> Block<'a> {
+    let border = if focused { accent } else { Color::White };
+    Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .border_style(Style::default().fg(border))
+        .title(title)
+        .border_type(BorderType::Plain)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn messages_block<'a>(
+    msgs: &VecDeque<Line>,
+    topic: Option<&str>,
+    focused: bool,
+    pretty_json: bool,
+    hex: Option<usize>,
+    search: &str,
+    searching: bool,
+    notes: &[String],
+    accent: Color,
+) -> List<'a> {
+    let mut title = match topic {
+        Some(topic) => format!("Messages — {}", topic),
+        None => "Messages".to_string(),
+    };
+    for note in notes {
+        title.push_str(&format!(" ({})", note));
+    }
+    if pretty_json {
+        title.push_str(" (JSON)");
+    }
+    if hex.is_some() {
+        title.push_str(" (hex)");
+    }
+    if searching || !search.is_empty() {
+        let found = msgs
+            .iter()
+            .filter(|msg| matches_search(msg, search))
+            .count();
+        let cursor = if searching { "_" } else { "" };
+        title.push_str(&format!(" /{}{} ({} found)", search, cursor, found));
+    }
+    let heading = pane_block(title, focused, accent);
+
+    let items: Vec<_> = msgs
+        .iter()
+        .enumerate()
+        .map(|(i, msg)| (msg, message_item(msg, pretty_json, hex == Some(i))))
+        .map(|(msg, item)| {
+            if matches_search(msg, search) {
+                item.style(Style::default().fg(Color::LightMagenta))
+            } else {
+                item
+            }
+        })
+        .collect();
+    List::new(items)
+        .block(heading)
+        .highlight_style(selected_style(accent))
+}
+
+fn message_item<'a>(msg: &Line, pretty_json: bool, hex: bool) -> ListItem<'a> {
+    match msg {
+        Line::Message(message) if hex => {
+            let mut lines = vec![Spans::from(message.heading())];
+            lines.extend(
+                message
+                    .hex_dump()
+                    .into_iter()
+                    .map(|line| Spans::from(format!("  {}", line))),
+            );
+            ListItem::new(lines)
+        }
+        Line::Message(message) if pretty_json => match message.pretty_value() {
+            Some(pretty) => {
+                let mut lines = vec![Spans::from(message.heading())];
+                lines.extend(
+                    pretty
+                        .lines()
+                        .map(|line| Spans::from(format!("  {}", line))),
+                );
+                ListItem::new(lines)
+            }
+            None => ListItem::new(msg.to_string()),
+        },
+        _ => ListItem::new(msg.to_string()),
+    }
+}
+
+/// Whether `line` is a message whose value contains `search`, ignoring case.
+fn matches_search(line: &Line, search: &str) -> bool {
+    match line {
+        Line::Message(message) if !search.is_empty() => message
+            .value
+            .to_lowercase()
+            .contains(&search.to_lowercase()),
+        _ => false,
+    }
+}
+
+/// Selects the next message matching `search` after the current selection,
+/// or the previous one before it, wrapping around at either end.
+fn jump_to_match(state: &mut ListState, msgs: &VecDeque<Line>, search: &str, forward: bool) {
+    let matches: Vec<usize> = (0..msgs.len())
+        .filter(|&i| matches_search(&msgs[i], search))
+        .collect();
+    let current = state.selected();
+    let target = if forward {
+        matches
+            .iter()
+            .find(|&&i| current.is_none_or(|c| i > c))
+            .or_else(|| matches.first())
+    } else {
+        matches
+            .iter()
+            .rev()
+            .find(|&&i| current.is_none_or(|c| i < c))
+            .or_else(|| matches.last())
+    };
+    if let Some(&i) = target {
+        state.select(Some(i));
+    }
+}
+
+/// The broker hosts colored by whether they answered at the last refresh.
+fn broker_info<'a>(brokers: &[String], health: &[(String, bool)]) -> Spans<'a> {
+    let mut spans = vec![Span::styled(
+        "Brokers: ",
+        Style::default().fg(Color::Yellow),
+    )];
+    for (i, broker) in brokers.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(", "));
+        }
+        let color = match health.iter().find(|(host, _)| host == broker) {
+            Some((_, true)) => Color::Green,
+            Some((_, false)) => Color::Red,
+            None => Color::Yellow,
+        };
+        spans.push(Span::styled(
+            broker.clone(),
+            Style::default()
+                .fg(color)
+                .add_modifier(Modifier::UNDERLINED),
+        ));
+    }
+    Spans::from(spans)
+}
+
+/// Partitions across all topics whose leader is down.
+fn leaderless_partitions(topic_list: &[TopicData]) -> usize {
+    topic_list
+        .iter()
+        .flat_map(|topic| &topic.partitions)
+        .filter(|p| !p.has_leader())
+        .count()
+}
+
+/// How many partitions each broker leads across all topics, busiest first.
+/// Partitions without a leader count toward no broker, and brokers leading
+/// nothing don't appear.
+fn leader_counts(topic_list: &[TopicData]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for p in topic_list.iter().flat_map(|topic| &topic.partitions) {
+        if p.has_leader() {
+            *counts.entry(&p.leader).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(host, count)| (host.to_string(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// The busiest broker and how many partitions it leads, if that is more
+/// than `LEADER_SKEW` times its fair share.
+fn leader_skew(counts: &[(String, usize)]) -> Option<(&str, usize)> {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let (host, busiest) = counts.first()?;
+    let fair = total as f64 / counts.len() as f64;
+    (counts.len() > 1 && *busiest as f64 > fair * LEADER_SKEW).then_some((host, *busiest))
+}
+
+/// One line per profile, with the selected one highlighted.
+fn profile_lines<'a>(names: &[String], selected: usize, accent: Color) -> Vec<Spans<'a>> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if i == selected {
+                Spans::from(Span::styled(name.clone(), selected_style(accent)))
+            } else {
+                Spans::from(name.clone())
+            }
+        })
+        .collect()
+}
+
+/// One line per broker with its share of the leaderships as a bar.
+fn leader_lines<'a>(counts: &[(String, usize)], accent: Color) -> Vec<Spans<'a>> {
+    if counts.is_empty() {
+        return vec![Spans::from("No partition has a leader")];
+    }
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let width = counts.iter().map(|(host, _)| host.len()).max().unwrap_or(0);
+    let skewed = leader_skew(counts).map(|(host, _)| host);
+    counts
+        .iter()
+        .map(|(host, count)| {
+            let share = *count as f64 / total as f64;
+            let style = if skewed == Some(host.as_str()) {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Spans::from(vec![
+                Span::styled(
+                    format!("{:<width$}  {:>6}  ", host, count, width = width),
+                    style,
+                ),
+                Span::styled(
+                    "█".repeat((share * 30.0).round() as usize),
+                    Style::default().fg(accent),
+                ),
+                Span::raw(format!(" {:.0}%", share * 100.0)),
+            ])
+        })
+        .collect()
+}
+
+fn num_topics_label(num: usize) -> String {
+    format!("{} {}", "Number of Topics:", num)
+}
+
+fn fetch_offset_label(offset: FetchOffset) -> String {
+    let mode = match offset {
+        FetchOffset::Latest => "Latest",
+        _ => "Earliest",
+    };
+    format!("{} {}", "Pull from:", mode)
+}
+
+fn group_label(group: &str, offset: FetchOffset) -> String {
+    match offset {
+        // Pulls from Latest don't join the group, see `create_consumer`.
+        FetchOffset::Latest => format!("Group: {} (unused)", group),
+        _ => format!("Group: {}", group),
+    }
+}
+
+/// Shortens large counts to a few significant digits, like `1.2M`.
+fn humanize(n: i64) -> String {
+    let units = [
+        (1_000_000_000_000, "T"),
+        (1_000_000_000, "B"),
+        (1_000_000, "M"),
+        (1_000, "k"),
+    ];
+    match units.iter().find(|(size, _)| n >= *size) {
+        Some((size, unit)) => format!("{:.1}{}", n as f64 / *size as f64, unit),
+        None => n.to_string(),
+    }
+}
+
+/// A size in bytes in the largest unit it fills, e.g. 340.2 MB.
+fn bytes(n: i64) -> String {
+    let units = [
+        (1 << 40, "TB"),
+        (1 << 30, "GB"),
+        (1 << 20, "MB"),
+        (1 << 10, "KB"),
+    ];
+    match units.iter().find(|(size, _)| n >= *size) {
+        Some((size, unit)) => format!("{:.1} {}", n as f64 / *size as f64, unit),
+        None => format!("{} B", n),
+    }
+}
+
+/// `n` with its digits grouped by thousands, e.g. 14,823,911.
+fn thousands(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if n < 0 {
+        grouped.insert(0, '-');
+    }
+    grouped
+}
+
+fn rate_label<'a>(topic: &str, rate: &Result<f64, String>) -> Spans<'a> {
+    match rate {
+        Ok(rate) if *rate == 0.0 => Spans::from(format!("{}: no new messages", topic)),
+        Ok(rate) if *rate < 10.0 => Spans::from(format!("{}: {:.1} msg/s", topic, rate)),
+        Ok(rate) => Spans::from(format!("{}: {} msg/s", topic, humanize(*rate as i64))),
+        Err(e) => Spans::from(Span::styled(
+            format!("Could not measure {}: {}", topic, e),
+            Style::default().fg(Color::Red),
+        )),
+    }
+}
+
+fn get_selected_topic(topic_list_state: &ListState, topic_list: &[TopicData]) -> Option<TopicData> {
+    topic_list_state
+        .selected()
+        .and_then(|selected| topic_list.get(selected))
+        .cloned()
+}
+
+/// The text typed to filter the topics. Text starting with `/` is a regex,
+/// compiled once whenever the text changes rather than on every draw.
+#[derive(Default)]
+struct TopicFilter {
+    text: String,
+    regex: Option<Result<Regex, PatternError>>,
+}
+
+impl TopicFilter {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.compile();
+    }
+
+    fn pop(&mut self) {
+        self.text.pop();
+        self.compile();
+    }
+
+    fn clear(&mut self) {
+        self.text.clear();
+        self.regex = None;
+    }
+
+    fn compile(&mut self) {
+        self.regex = self.text.strip_prefix('/').map(Regex::new);
+    }
+
+    /// Whether `name` contains the text, ignoring case, or matches the
+    /// regex. A regex that doesn't compile is matched as text instead.
+    fn matches(&self, name: &str) -> bool {
+        match &self.regex {
+            Some(Ok(regex)) => regex.is_match(name),
+            _ => {
+                let literal = self.text.strip_prefix('/').unwrap_or(&self.text);
+                name.to_lowercase().contains(&literal.to_lowercase())
+            }
+        }
+    }
+
+    fn error(&self) -> Option<&PatternError> {
+        self.regex.as_ref().and_then(|regex| regex.as_ref().err())
+    }
+}
+
+/// The topics `filter` matches.
+fn filter_topics(topic_list: &[TopicData], filter: &TopicFilter) -> Vec<TopicData> {
+    topic_list
+        .iter()
+        .filter(|topic| filter.matches(&topic.name))
+        .cloned()
+        .collect()
+}
+
+/// Re-applies `filter` after it or the topics changed, keeping the
+/// previously selected topic selected if it still matches and otherwise
+/// clamping the selection to the remaining topics.
+fn reselect_topic(
+    topic_list_state: &mut ListState,
+    previous: &[TopicData],
+    topic_list: &[TopicData],
+    filter: &TopicFilter,
+) {
+    let selected = get_selected_topic(topic_list_state, previous).map(|t| t.name);
+    let filtered = filter_topics(topic_list, filter);
+    match filtered
+        .iter()
+        .position(|t| Some(&t.name) == selected.as_ref())
+    {
+        Some(index) => topic_list_state.select(Some(index)),
+        // The selected topic is gone, so stay near where it was.
+        None => clamp_selection(topic_list_state, filtered.len()),
+    }
+}
+
+/// Whether `c` is bound to a command in `KEYMAP`, rather than free to start
+/// a jump to a topic.
+fn is_command_key(c: char) -> bool {
+    let mut buf = [0; 4];
+    let c: &str = c.encode_utf8(&mut buf);
+    KEYMAP
+        .iter()
+        .any(|binding| binding.key.split(" / ").any(|key| key == c))
+}
+
+/// The first topic whose name starts with `prefix`, ignoring case.
+fn find_prefix(topics: &[TopicData], prefix: &str) -> Option<usize> {
+    let prefix = prefix.to_lowercase();
+    topics
+        .iter()
+        .position(|t| t.name.to_lowercase().starts_with(&prefix))
+}
+
+/// Keeps only the last message of each key, as log compaction would, and
+/// drops keys whose last message is a tombstone, a message without a value.
+/// Notices and messages without a key stay. Returns whether any line went.
+fn compact_lines(msgs: &mut VecDeque<Line>) -> bool {
+    let mut last: HashMap<String, usize> = HashMap::new();
+    for (i, line) in msgs.iter().enumerate() {
+        if let Line::Message(ConsumedMessage { key: Some(key), .. }) = line {
+            last.insert(key.clone(), i);
+        }
+    }
+    let before = msgs.len();
+    let mut i = 0;
+    msgs.retain(|line| {
+        let keep = match line {
+            Line::Message(ConsumedMessage {
+                key: Some(key),
+                value,
+                ..
+            }) => last.get(key) == Some(&i) && !value.is_empty(),
+            _ => true,
+        };
+        i += 1;
+        keep
+    });
+    msgs.len() < before
+}
+
+/// Whether the topic's `cleanup.policy` includes compaction.
+fn is_compacted(configs: &[TopicConfig]) -> bool {
+    configs.iter().any(|config| {
+        config.name == "cleanup.policy"
+            && config
+                .value
+                .as_deref()
+                .is_some_and(|policy| policy.contains("compact"))
+    })
+}
+
+fn compact_hint(topic: &str) -> String {
+    format!(
+        "{} is compacted: press z to keep only the latest message of each key",
+        topic
+    )
+}
+
+/// Keeps a list's selection on one of its `len` items after the list changed
+/// size: the last item if it was past the end, the first if there was none,
+/// and nothing once the list is empty.
+fn clamp_selection(state: &mut ListState, len: usize) {
+    state.select(match len {
+        0 => None,
+        _ => Some(state.selected().unwrap_or(0).min(len - 1)),
+    });
+}
+
+fn topics_title(filter: &TopicFilter, filtering: bool) -> String {
+    let mut title = match (filter.text.is_empty(), filtering) {
+        (true, false) => "Topics".to_string(),
+        (_, true) => format!("Topics /{}_", filter.text),
+        (false, false) => format!("Topics /{}", filter.text),
+    };
+    if let Some(e) = filter.error() {
+        title.push_str(&format!(" ({}, matching as text)", e));
+    }
+    title
+}
+
+fn render_groups<'a>(groups: &GroupsView, focused: bool, accent: Color) -> List<'a> {
+    let items: Vec<_> = groups
+        .groups
+        .iter()
+        .map(|group| ListItem::new(group.clone()))
+        .collect();
+    List::new(items)
+        .block(pane_block("Consumer groups".to_string(), focused, accent))
+        .highlight_style(selected_style(accent))
+}
+
+/// The offsets a group committed, with its lag against the latest offsets
+/// from the last refresh.
+fn render_group_offsets<'a>(
+    offsets: &[GroupOffset],
+    topic_list: &[TopicData],
+    accent: Color,
+) -> Table<'a> {
+    let rows: Vec<Row> = offsets
+        .iter()
+        .map(|o| {
+            let latest = topic_list
+                .iter()
+                .find(|t| t.name == o.topic)
+                .and_then(|t| t.partitions.iter().find(|p| p.id == o.partition))
+                .map(|p| p.offset);
+            Row::new(vec![
+                Cell::from(Span::raw(o.topic.clone())),
+                Cell::from(Span::raw(o.partition.to_string())),
+                Cell::from(Span::raw(o.offset.to_string())),
+                Cell::from(Span::raw(
+                    latest
+                        .map(|latest| (latest - o.offset).max(0).to_string())
+                        .unwrap_or_else(|| "-".to_string()),
+                )),
+            ])
+        })
+        .collect();
+
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    Table::new(rows)
+        .header(Row::new(vec![
+            Cell::from(Span::styled("Topic", bold)),
+            Cell::from(Span::styled("Partition", bold)),
+            Cell::from(Span::styled("Committed", bold)),
+            Cell::from(Span::styled("Lag", bold)),
+        ]))
+        .block(pane_block("Committed offsets".to_string(), false, accent))
         .widths(&[
+            Constraint::Percentage(40),
             Constraint::Percentage(20),
             Constraint::Percentage(20),
             Constraint::Percentage(20),
-            Constraint::Percentage(5),
-            Constraint::Percentage(5),
-        ]);
+        ])
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_topics<'a>(
+    topic_list_state: &ListState,
+    topic_list: &[TopicData],
+    title: &str,
+    focus: Focus,
+    columns: &'a TopicColumns,
+    exact_offsets: bool,
+    selected_partition: Option<usize>,
+    accent: Color,
+) -> (List<'a>, Table<'a>) {
+    let topics = pane_block(title.to_string(), focus == Focus::Topics, accent);
+
+    let mut items: Vec<_> = topic_list
+        .iter()
+        .map(|topic| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(topic.name.clone(), Style::default()),
+                Span::styled(
+                    match topic.size() {
+                        Some(size) => format!(
+                            " ({}p, ~{}, {})",
+                            topic.partitions.len(),
+                            humanize(topic.message_count()),
+                            bytes(size)
+                        ),
+                        None => format!(
+                            " ({}p, ~{})",
+                            topic.partitions.len(),
+                            humanize(topic.message_count())
+                        ),
+                    },
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Span::styled(
+            "No topics",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let selected_topic = get_selected_topic(topic_list_state, topic_list);
+
+    let list = List::new(items)
+        .block(topics)
+        .highlight_style(selected_style(accent));
+
+    let offset = |n: i64| {
+        if exact_offsets {
+            n.to_string()
+        } else {
+            thousands(n)
+        }
+    };
+    let detail_title = match (&selected_topic, selected_partition) {
+        (Some(topic), Some(row)) => format!(
+            "Detail (partition {} of {})",
+            row + 1,
+            topic.partitions.len()
+        ),
+        _ => "Detail".to_string(),
+    };
+    let has_topic = selected_topic.is_some();
+    let rows: Vec<Row> = selected_topic
+        .map(|topic| topic.partitions)
+        .unwrap_or_default()
+        .iter()
+        .map(|p| {
+            let row = Row::new(columns.columns.iter().map(|column| {
+                Cell::from(Span::raw(match column {
+                    TopicColumn::Partition => p.id.to_string(),
+                    TopicColumn::Leader => p.leader.to_string(),
+                    TopicColumn::Available => p.available.to_string(),
+                    TopicColumn::Replicas if p.replicas.is_empty() => "-".to_string(),
+                    TopicColumn::Replicas => p
+                        .replicas
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    TopicColumn::Isr if p.replicas.is_empty() => "-".to_string(),
+                    TopicColumn::Isr => {
+                        let isr = format!("ISR {}/{}", p.isr.len(), p.replicas.len());
+                        if p.is_under_replicated() {
+                            return Cell::from(Span::styled(
+                                isr,
+                                Style::default()
+                                    .fg(Color::Yellow)
+                                    .add_modifier(Modifier::BOLD),
+                            ));
+                        }
+                        isr
+                    }
+                    TopicColumn::Offset => offset(p.offset),
+                    TopicColumn::Retained => offset(p.retained()),
+                    TopicColumn::Lag => p.lag().map(offset).unwrap_or_else(|| "-".to_string()),
+                    TopicColumn::Size => p.size.map(bytes).unwrap_or_else(|| "-".to_string()),
+                }))
+            }));
+            if p.has_leader() {
+                row
+            } else {
+                row.style(Style::default().fg(Color::Red))
+            }
+        })
+        .collect();
+
+    let header = Row::new(columns.columns.iter().map(|column| {
+        Cell::from(Span::styled(
+            column_layout(*column).0,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+    }));
+    let topic_detail = Table::new(rows)
+        .block(pane_block(detail_title, focus == Focus::Detail, accent))
+        .highlight_style(selected_style(accent))
+        .widths(&columns.widths);
+    // Without a topic there is nothing to head.
+    let topic_detail = if has_topic {
+        topic_detail.header(header)
+    } else {
+        topic_detail
+    };
 
     (list, topic_detail)
 }
+
+/// The configured columns of the partition table, with the widths they get.
+/// Built once, as the table borrows its widths.
+struct TopicColumns {
+    columns: Vec<TopicColumn>,
+    widths: Vec<Constraint>,
+}
+
+impl TopicColumns {
+    fn new(columns: Vec<TopicColumn>) -> TopicColumns {
+        // Columns keep their share of the width relative to each other.
+        let total: u16 = columns.iter().map(|column| column_layout(*column).1).sum();
+        let widths = columns
+            .iter()
+            .map(|column| Constraint::Percentage(column_layout(*column).1 * 98 / total.max(1)))
+            .collect();
+        TopicColumns { columns, widths }
+    }
+}
+
+/// The heading of a partition table column and its relative width.
+fn column_layout(column: TopicColumn) -> (&'static str, u16) {
+    match column {
+        TopicColumn::Partition => ("Partition Id", 12),
+        TopicColumn::Leader => ("Leader", 22),
+        TopicColumn::Available => ("Available", 11),
+        TopicColumn::Replicas => ("Replicas", 12),
+        TopicColumn::Isr => ("In Sync", 10),
+        TopicColumn::Offset => ("Offset", 19),
+        TopicColumn::Retained => ("Retained", 17),
+        TopicColumn::Lag => ("Lag", 17),
+        TopicColumn::Size => ("Size", 10),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_ahead_starts_on_keys_without_a_command() {
+        for c in ['a', 'z', 'P', 'B', 'C', 'N', '/', '?'] {
+            assert!(is_command_key(c), "{}", c);
+        }
+        for c in ['O', 'T', '2', '-', '_', '.'] {
+            assert!(!is_command_key(c), "{}", c);
+        }
+        let topics: Vec<TopicData> = ["2024-events", "Orders", "orders.dlq", "payments"]
+            .iter()
+            .map(|name| TopicData {
+                name: name.to_string(),
+                partitions: vec![],
+            })
+            .collect();
+        assert_eq!(find_prefix(&topics, "O"), Some(1));
+        assert_eq!(find_prefix(&topics, "ORDERS."), Some(2));
+        assert_eq!(find_prefix(&topics, "2"), Some(0));
+        assert_eq!(find_prefix(&topics, "x"), None);
+    }
+}
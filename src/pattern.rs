@@ -0,0 +1,534 @@
+//! A small regular expression matcher for filtering topic names and message
+//! keys, since the
+//! regex crate isn't a dependency. It supports the common syntax: `.`, `^`,
+//! `$`, classes like `[a-z]` and `[^.]`, `\d`, `\w` and `\s`, groups,
+//! alternation with `|`, and the `*`, `+`, `?` and `{n,m}` quantifiers.
+//! Patterns compile to a program run as a Pike VM, which follows every way
+//! of matching in step, so matching takes time linear in the name's length
+//! whatever the pattern.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("unclosed (")]
+    UnclosedGroup,
+    #[error("unmatched )")]
+    UnmatchedParen,
+    #[error("unclosed [")]
+    UnclosedClass,
+    #[error("invalid range {0}-{1}")]
+    InvalidRange(char, char),
+    #[error("nothing to repeat before {0}")]
+    NothingToRepeat(char),
+    #[error("trailing \\")]
+    TrailingEscape,
+    #[error("pattern is too large")]
+    TooLarge,
+}
+
+/// Programs longer than this are refused, as `x{1000}{1000}` would
+/// otherwise take a million instructions.
+const MAX_PROGRAM: usize = 10_000;
+/// The largest count a `{n,m}` quantifier may have.
+const MAX_REPEAT: u32 = 1_000;
+
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+/// An instruction of a compiled pattern.
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Continues at both instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => c.is_alphanumeric() || c == '_',
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Range(low, high) => (low..=high).contains(&c),
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        });
+        found != self.negated
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, PatternError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.peek().is_some() {
+            return Err(PatternError::UnmatchedParen);
+        }
+        let mut program = vec![];
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.run(text).0
+    }
+
+    /// Whether the pattern matches anywhere in `text`, and how many threads
+    /// it took to tell. Each instruction runs at most once per position, so
+    /// that is never more than one per instruction and character.
+    fn run(&self, text: &str) -> (bool, usize) {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let steps = |current: &Threads, next: &Threads| current.added + next.added;
+        for pos in 0..=chars.len() {
+            // A match may start at any position.
+            if self.add(&mut current, 0, pos, chars.len()) {
+                return (true, steps(&current, &next));
+            }
+            let c = match chars.get(pos) {
+                Some(&c) => c,
+                None => break,
+            };
+            for &pc in &current.pcs {
+                let step = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c),
+                    _ => false,
+                };
+                if step && self.add(&mut next, pc + 1, pos + 1, chars.len()) {
+                    return (true, steps(&current, &next));
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        (false, steps(&current, &next))
+    }
+
+    /// Adds a thread at `pc` to `threads`, following jumps, splits and
+    /// anchors to the instructions that consume a character. Returns whether
+    /// one of them reached `Match`.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            // Each instruction is only followed once per position, which
+            // also stops loops around quantifiers that match nothing.
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+/// The instructions reached at one position, each at most once.
+struct Threads {
+    pcs: Vec<usize>,
+    seen: Vec<bool>,
+    /// How many were ever added, across clears.
+    added: usize,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            pcs: Vec::with_capacity(len),
+            seen: vec![false; len],
+            added: 0,
+        }
+    }
+
+    /// Adds `pc`, unless it was added already.
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.pcs.push(pc);
+        self.added += 1;
+        true
+    }
+
+    fn clear(&mut self) {
+        for &pc in &self.pcs {
+            self.seen[pc] = false;
+        }
+        self.pcs.clear();
+    }
+}
+
+/// Appends the instructions matching `node` to `program`.
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), PatternError> {
+    if program.len() > MAX_PROGRAM {
+        return Err(PatternError::TooLarge);
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alt(branches) => {
+            let mut jumps = vec![];
+            let (last, rest) = branches.split_last().expect("alternations have branches");
+            for branch in rest {
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(branch, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            compile(last, program)?;
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        }
+        Node::Repeat(node, min, max) => {
+            // Checked here as well, since a repeated group that is empty
+            // never grows the program.
+            if max.unwrap_or(*min) > MAX_REPEAT {
+                return Err(PatternError::TooLarge);
+            }
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    for _ in *min..*max {
+                        let split = program.len();
+                        program.push(Inst::Split(split + 1, 0));
+                        compile(node, program)?;
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternation(&mut self) -> Result<Node, PatternError> {
+        let mut branches = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.remove(0),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, PatternError> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom));
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn quantified(&mut self, mut atom: Node) -> Node {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.counts() {
+                    Some(counts) => counts,
+                    None => return atom,
+                },
+                _ => return atom,
+            };
+            self.pos += 1;
+            // Lazy quantifiers match the same names, only differently.
+            if self.peek() == Some('?') {
+                self.pos += 1;
+            }
+            atom = Node::Repeat(Box::new(atom), min, max);
+        }
+    }
+
+    /// Reads `{n}`, `{n,}` or `{n,m}`, leaving the position on the closing
+    /// brace. Anything else is left alone to be read as literal characters.
+    fn counts(&mut self) -> Option<(u32, Option<u32>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let end = rest.find('}')?;
+        let (min, max) = match rest[..end].split_once(',') {
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+            None => {
+                let n = rest[..end].parse().ok()?;
+                (n, Some(n))
+            }
+        };
+        if max.is_some_and(|max| max < min) {
+            return None;
+        }
+        self.pos += rest[..end].chars().count() + 1;
+        Some((min, max))
+    }
+
+    fn atom(&mut self) -> Result<Node, PatternError> {
+        let c = self.next().unwrap_or_default();
+        Ok(match c {
+            '(' => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let inner = self.alternation()?;
+                if self.next() != Some(')') {
+                    return Err(PatternError::UnclosedGroup);
+                }
+                inner
+            }
+            '[' => Node::Class(self.class()?),
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '*' | '+' | '?' => return Err(PatternError::NothingToRepeat(c)),
+            '\\' => match self.escape()? {
+                ClassItem::Perl(perl, negated) => Node::Class(Class {
+                    negated: false,
+                    items: vec![ClassItem::Perl(perl, negated)],
+                }),
+                ClassItem::Range(c, _) => Node::Char(c),
+            },
+            c => Node::Char(c),
+        })
+    }
+
+    /// Reads the character after a `\`, as a class for `\d` and the like or
+    /// a range of one character otherwise.
+    fn escape(&mut self) -> Result<ClassItem, PatternError> {
+        let c = self.next().ok_or(PatternError::TrailingEscape)?;
+        Ok(match c {
+            'd' | 'D' => ClassItem::Perl(Perl::Digit, c == 'D'),
+            'w' | 'W' => ClassItem::Perl(Perl::Word, c == 'W'),
+            's' | 'S' => ClassItem::Perl(Perl::Space, c == 'S'),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Class, PatternError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = vec![];
+        loop {
+            let item = match self.next() {
+                None => return Err(PatternError::UnclosedClass),
+                // A ] right after the [ is a literal one.
+                Some(']') if !items.is_empty() => break,
+                Some('\\') => self.escape()?,
+                Some(c) => ClassItem::Range(c, c),
+            };
+            let low = match item {
+                ClassItem::Range(low, _) if self.peek() == Some('-') => low,
+                item => {
+                    items.push(item);
+                    continue;
+                }
+            };
+            let high = match self.chars.get(self.pos + 1) {
+                Some(']') | None => {
+                    items.push(item);
+                    continue;
+                }
+                Some(_) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some('\\') => match self.escape()? {
+                            ClassItem::Range(high, _) => high,
+                            _ => return Err(PatternError::InvalidRange(low, '\\')),
+                        },
+                        high => high.unwrap_or_default(),
+                    }
+                }
+            };
+            if high < low {
+                return Err(PatternError::InvalidRange(low, high));
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+        Ok(Class { negated, items })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn matches_anywhere_in_the_text() {
+        assert!(is_match("orders", "prod.orders.v1"));
+        assert!(is_match("ord.rs", "orders"));
+        assert!(is_match("v\\d+", "orders.v12"));
+        assert!(is_match("[a-c]x|dlq", "orders.dlq"));
+        assert!(is_match("(in|out)bound", "payments.outbound"));
+        assert!(is_match("a{2,3}", "baab"));
+        assert!(is_match("", "anything"));
+        assert!(!is_match("a{3}", "baab"));
+        assert!(!is_match("[^a-z.]", "orders.dlq"));
+        assert!(!is_match("\\s", "orders"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match(r"^orders\..*\.dlq$", "orders.eu.dlq"));
+        assert!(!is_match(r"^orders\..*\.dlq$", "old.orders.eu.dlq"));
+        assert!(!is_match(r"^orders\..*\.dlq$", "orders.eu.dlq.retry"));
+        assert!(is_match("^$", ""));
+        assert!(!is_match("^$", "x"));
+        assert!(is_match("^(a|b)*$", "abba"));
+        assert!(!is_match("^(a|b)*$", "abc"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for (pattern, error) in [
+            ("(orders", "unclosed ("),
+            ("orders)", "unmatched )"),
+            ("[a-", "unclosed ["),
+            ("[z-a]", "invalid range z-a"),
+            ("*orders", "nothing to repeat before *"),
+            ("orders\\", "trailing \\"),
+            ("x{1001}", "pattern is too large"),
+            ("(x{1000}){1000}", "pattern is too large"),
+            ("(){1000000}", "pattern is too large"),
+        ] {
+            match Regex::new(pattern) {
+                Err(e) => assert_eq!(e.to_string(), error, "{}", pattern),
+                Ok(_) => panic!("{} compiled", pattern),
+            }
+        }
+        // Braces that aren't a count are literal.
+        assert!(is_match("a{x}", "a{x}"));
+    }
+
+    #[test]
+    fn nested_quantifiers_take_linear_time() {
+        let a28 = "a".repeat(28);
+        let long = "x".repeat(249);
+        // Backtracking took many seconds, and exponentially many steps, on
+        // each of these.
+        let cases = [
+            ("(a|a)*$x", &a28, false),
+            ("(a*)*b", &a28, false),
+            ("(.*)*x", &a28, false),
+            (".*.*.*y", &long, false),
+            ("^(a*)*$", &a28, true),
+            ("(x+x+)+$", &long, true),
+        ];
+        for (pattern, text, expected) in cases {
+            let regex = Regex::new(pattern).unwrap();
+            let (matched, steps) = regex.run(text);
+            assert_eq!(matched, expected, "{}", pattern);
+            let bound = (text.len() + 1) * regex.program.len();
+            assert!(steps <= bound, "{} took {} steps", pattern, steps);
+        }
+    }
+}
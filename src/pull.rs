@@ -0,0 +1,181 @@
+use crate::decode::Decoder;
+use crate::kafka_client::{ConsumedMessage, SimpleKafkaClient};
+use kafka::consumer::{Consumer, FetchOffset};
+use log::{debug, warn};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// What the UI asks of the thread running a pull session.
+enum Command {
+    /// Poll once, taking at most this many messages.
+    Pull(usize),
+    /// Commit what was consumed, unless the session doesn't commit, and stop.
+    Close,
+}
+
+/// What the thread running a pull session reports back.
+pub enum PullEvent {
+    /// The messages of one poll, and whether the limit cut it short.
+    Pulled(Vec<ConsumedMessage>, bool),
+    /// Connecting or polling failed; the session is over.
+    Failed(kafka::Error),
+}
+
+/// A consumer kept open between pulls on its own thread, so that repeated
+/// pulls of the same topic continue where the last one stopped instead of
+/// reconnecting, and a slow broker doesn't freeze the UI.
+pub struct PullSession {
+    pub topic: String,
+    partition: Option<i32>,
+    offset: FetchOffset,
+    commands: Sender<Command>,
+    events: Receiver<PullEvent>,
+    closed: Receiver<kafka::Result<()>>,
+    /// Whether a pull was asked for and hasn't been answered yet.
+    busy: bool,
+}
+
+impl PullSession {
+    /// Starts reading `topic`, or only `partition` of it. The consumer
+    /// connects on the session's thread, so failing to connect shows up as
+    /// a `Failed` event. Without `commit`, the group's offsets are read to
+    /// start from but never moved, so other consumers of it aren't affected.
+    pub fn open(
+        client: &SimpleKafkaClient,
+        topic: &str,
+        partition: Option<i32>,
+        group: &str,
+        offset: FetchOffset,
+        commit: bool,
+    ) -> PullSession {
+        let builder = client.consumer_builder(topic, partition, group, offset);
+        let decoder = client.decoder();
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let (closed_tx, closed) = mpsc::channel();
+        let name = topic.to_string();
+        thread::spawn(move || {
+            let mut consumer = match builder.create() {
+                Ok(consumer) => consumer,
+                Err(e) => {
+                    warn!("could not create a consumer for {}: {}", name, e);
+                    let _ = event_tx.send(PullEvent::Failed(e));
+                    return;
+                }
+            };
+            for command in command_rx {
+                match command {
+                    Command::Pull(limit) => {
+                        let event = match pull(&mut consumer, limit, &decoder, commit) {
+                            Ok((messages, full)) => {
+                                // Followed topics are polled every tick, mostly for nothing.
+                                if !messages.is_empty() {
+                                    debug!("polled {} messages from {}", messages.len(), name);
+                                }
+                                PullEvent::Pulled(messages, full)
+                            }
+                            Err(e) => {
+                                warn!("could not poll {}: {}", name, e);
+                                PullEvent::Failed(e)
+                            }
+                        };
+                        let failed = matches!(event, PullEvent::Failed(_));
+                        if event_tx.send(event).is_err() || failed {
+                            return;
+                        }
+                    }
+                    Command::Close if commit => {
+                        let _ = closed_tx.send(consumer.commit_consumed());
+                        return;
+                    }
+                    Command::Close => {
+                        let _ = closed_tx.send(Ok(()));
+                        return;
+                    }
+                }
+            }
+            // The session was dropped without being closed.
+            if commit {
+                let _ = consumer.commit_consumed();
+            }
+        });
+        PullSession {
+            topic: topic.to_string(),
+            partition,
+            offset,
+            commands,
+            events,
+            closed,
+            busy: false,
+        }
+    }
+
+    pub fn reads(&self, topic: &str, partition: Option<i32>, offset: FetchOffset) -> bool {
+        self.topic == topic
+            && self.partition == partition
+            && mem::discriminant(&self.offset) == mem::discriminant(&offset)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Asks for one poll of at most `limit` messages, unless one is already
+    /// underway. The result arrives through `try_event`.
+    pub fn pull(&mut self, limit: usize) {
+        if !self.busy && self.commands.send(Command::Pull(limit)).is_ok() {
+            self.busy = true;
+        }
+    }
+
+    /// The next report from the session's thread, if one has arrived.
+    pub fn try_event(&mut self) -> Option<PullEvent> {
+        match self.events.try_recv() {
+            Ok(event) => {
+                self.busy = false;
+                Some(event)
+            }
+            Err(TryRecvError::Empty) => None,
+            // The thread only stops early after reporting a failure.
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Commits anything consumed but not yet committed and stops the thread,
+    /// waiting for a poll that is underway to finish first.
+    pub fn close(self) -> kafka::Result<()> {
+        if self.commands.send(Command::Close).is_err() {
+            return Ok(());
+        }
+        // A thread that failed has nothing left to commit.
+        self.closed.recv().unwrap_or(Ok(()))
+    }
+}
+
+/// Polls once, taking at most `limit` of the messages that arrived and
+/// committing them if `commit` is set. Also returns whether the limit cut the
+/// poll short; the rest is picked up again from the committed offsets.
+fn pull(
+    consumer: &mut Consumer,
+    limit: usize,
+    decoder: &Decoder,
+    commit: bool,
+) -> kafka::Result<(Vec<ConsumedMessage>, bool)> {
+    let mut messages = vec![];
+    let mut full = false;
+    'poll: for ms in consumer.poll()?.iter() {
+        for m in ms.messages() {
+            if messages.len() == limit {
+                full = true;
+                break 'poll;
+            }
+            messages.push(ConsumedMessage::from_kafka(ms.partition(), m, decoder));
+            consumer.consume_message(ms.topic(), ms.partition(), m.offset)?;
+        }
+    }
+    if commit {
+        consumer.commit_consumed()?;
+    }
+    Ok((messages, full))
+}